@@ -0,0 +1,183 @@
+//! Raw attack patterns, worked out from piece roles independently of the engine.
+
+use crate::{coords, pieces, Board, BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+
+const KNIGHT_STEPS: [(i16, i16); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+const KING_STEPS: [(i16, i16); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const ORTHOGONAL: [(i16, i16); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONAL: [(i16, i16); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+/// Squares attacked by the piece on `square` (row, column), including squares
+/// held by its own side. Sliding pieces stop at the first occupied square.
+pub fn attacked_squares(board: &Board, square: (usize, usize)) -> Vec<(usize, usize)> {
+    let piece = match board[square.0][square.1] {
+        Some(piece) => piece,
+        None => return Vec::new(),
+    };
+    let (row, col) = (square.0 as i16, square.1 as i16);
+    let mut squares = Vec::new();
+
+    match piece.role {
+        PAWN => {
+            // white pawns move towards row 0
+            let forward = if piece.is_white { -1 } else { 1 };
+            step(row, col, &[(forward, -1), (forward, 1)], &mut squares);
+        }
+        KNIGHT => step(row, col, &KNIGHT_STEPS, &mut squares),
+        KING => step(row, col, &KING_STEPS, &mut squares),
+        BISHOP => slide(board, row, col, &DIAGONAL, &mut squares),
+        ROOK => slide(board, row, col, &ORTHOGONAL, &mut squares),
+        QUEEN => {
+            slide(board, row, col, &DIAGONAL, &mut squares);
+            slide(board, row, col, &ORTHOGONAL, &mut squares);
+        }
+        _ => (),
+    }
+
+    squares
+}
+
+/// Whether any piece of the given colour attacks `square`.
+pub fn is_attacked(board: &Board, square: (usize, usize), by_white: bool) -> bool {
+    pieces(board).any(|(from, piece)| {
+        piece.is_white == by_white && attacked_squares(board, from).contains(&square)
+    })
+}
+
+/// Location of the king of the given colour, if it is on the board.
+pub fn king_square(board: &Board, is_white: bool) -> Option<(usize, usize)> {
+    pieces(board)
+        .find(|(_, piece)| piece.role == KING && piece.is_white == is_white)
+        .map(|(square, _)| square)
+}
+
+/// Whether the king of the given colour is attacked.
+pub fn in_check(board: &Board, is_white: bool) -> bool {
+    king_square(board, is_white).is_some_and(|square| is_attacked(board, square, !is_white))
+}
+
+/// (row, column) as board indices, if the square is on the board.
+fn board_square(row: i16, col: i16) -> Option<(usize, usize)> {
+    let square = (usize::try_from(row).ok()?, usize::try_from(col).ok()?);
+    coords::on_board(square).then_some(square)
+}
+
+fn step(row: i16, col: i16, offsets: &[(i16, i16)], squares: &mut Vec<(usize, usize)>) {
+    for (d_row, d_col) in offsets {
+        if let Some(square) = board_square(row + d_row, col + d_col) {
+            squares.push(square);
+        }
+    }
+}
+
+fn slide(
+    board: &Board,
+    row: i16,
+    col: i16,
+    directions: &[(i16, i16)],
+    squares: &mut Vec<(usize, usize)>,
+) {
+    for (d_row, d_col) in directions {
+        let (mut r, mut c) = (row + d_row, col + d_col);
+        while let Some(square) = board_square(r, c) {
+            squares.push(square);
+            if board[square.0][square.1].is_some() {
+                break;
+            }
+            r += d_row;
+            c += d_col;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{empty_board, Piece, GRID_SIZE};
+
+    fn board_with(pieces: &[((usize, usize), u8, bool)]) -> Board {
+        let mut board = empty_board(GRID_SIZE as usize);
+        for &((row, col), role, is_white) in pieces {
+            board[row][col] = Some(Piece::new(role, (row as i16, col as i16), is_white));
+        }
+        board
+    }
+
+    fn sorted(mut squares: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        squares.sort();
+        squares
+    }
+
+    #[test]
+    fn sliders_stop_on_and_include_a_friendly_blocker() {
+        // white rook on a1 with its own knight on a4
+        let board = board_with(&[((7, 0), ROOK, true), ((4, 0), KNIGHT, true)]);
+        let attacked = attacked_squares(&board, (7, 0));
+        assert!(attacked.contains(&(4, 0)));
+        assert!(!attacked.contains(&(3, 0)));
+        assert!(attacked.contains(&(6, 0)) && attacked.contains(&(7, 7)));
+        assert_eq!(attacked.len(), 3 + 7);
+    }
+
+    #[test]
+    fn pawns_attack_diagonally_forward() {
+        let board = board_with(&[((6, 4), PAWN, true), ((1, 3), PAWN, false)]);
+        assert_eq!(
+            sorted(attacked_squares(&board, (6, 4))),
+            vec![(5, 3), (5, 5)]
+        );
+        assert_eq!(
+            sorted(attacked_squares(&board, (1, 3))),
+            vec![(2, 2), (2, 4)]
+        );
+    }
+
+    #[test]
+    fn pawns_on_the_edge_attack_one_square() {
+        let board = board_with(&[((6, 0), PAWN, true)]);
+        assert_eq!(attacked_squares(&board, (6, 0)), vec![(5, 1)]);
+    }
+
+    #[test]
+    fn knights_on_the_edge_stay_on_the_board() {
+        let board = board_with(&[((7, 0), KNIGHT, true), ((3, 7), KNIGHT, false)]);
+        assert_eq!(
+            sorted(attacked_squares(&board, (7, 0))),
+            vec![(5, 1), (6, 2)]
+        );
+        assert_eq!(
+            sorted(attacked_squares(&board, (3, 7))),
+            vec![(1, 6), (2, 5), (4, 5), (5, 6)]
+        );
+    }
+
+    #[test]
+    fn check_comes_from_an_enemy_piece() {
+        let board = board_with(&[((7, 4), KING, true), ((0, 4), ROOK, false)]);
+        assert!(in_check(&board, true));
+        let blocked = board_with(&[
+            ((7, 4), KING, true),
+            ((0, 4), ROOK, false),
+            ((6, 4), PAWN, true),
+        ]);
+        assert!(!in_check(&blocked, true));
+    }
+}
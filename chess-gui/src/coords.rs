@@ -0,0 +1,119 @@
+//! Conversions between board indices, algebraic squares and UCI moves.
+//!
+//! Board indices are (row, column) pairs with row 0 on the eighth rank and
+//! column 0 on the a-file. Squares are written the way the engine expects,
+//! in upper case ("E2"), and read in either case.
+
+use crate::GRID_SIZE;
+
+/// Whether (row, column) lies on the engine's board.
+pub fn on_board(square: (usize, usize)) -> bool {
+    square.0 < GRID_SIZE as usize && square.1 < GRID_SIZE as usize
+}
+
+/// (row, column) of a square such as "E2" or "e2".
+pub fn parse_square(file_rank: &str) -> Option<(usize, usize)> {
+    let mut chars = file_rank.chars();
+    let file = chars.next()?.to_ascii_uppercase();
+    let rank = chars.next()?.to_digit(10)? as usize;
+    if !('A'..='H').contains(&file) || !(1..=8).contains(&rank) || chars.next().is_some() {
+        return None;
+    }
+    Some((GRID_SIZE as usize - rank, file as usize - 'A' as usize))
+}
+
+/// Engine name ("E2") of the square at (row, column), which must be on the board.
+pub fn square_name(square: (usize, usize)) -> String {
+    format!(
+        "{}{}",
+        (b'A' + square.1 as u8) as char,
+        GRID_SIZE as usize - square.0
+    )
+}
+
+/// (row, column) of each square in a list such as the engine's legal moves,
+/// skipping any that can't be read.
+pub fn parse_squares(squares: &[String]) -> Vec<(usize, usize)> {
+    squares
+        .iter()
+        .filter_map(|square| parse_square(square))
+        .collect()
+}
+
+/// UCI long algebraic form of a move, such as "e2e4".
+// the inverse of `parse_uci_move`, which nothing writes out yet
+#[allow(dead_code)]
+pub fn uci_move(from: (usize, usize), to: (usize, usize)) -> String {
+    (square_name(from) + &square_name(to)).to_lowercase()
+}
+
+/// (row, column) of the start and end squares of a UCI move such as "e2e4".
+/// A trailing promotion letter ("e7e8q") is checked but not returned; callers
+/// that need it read the fifth character.
+pub fn parse_uci_move(uci: &str) -> Option<((usize, usize), (usize, usize))> {
+    if !uci.is_ascii() {
+        return None;
+    }
+    let promotion = match uci.len() {
+        4 => None,
+        5 => uci.chars().last(),
+        _ => return None,
+    };
+    if promotion.is_some_and(|piece| !"qrbnQRBN".contains(piece)) {
+        return None;
+    }
+    Some((parse_square(&uci[0..2])?, parse_square(&uci[2..4])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_square_round_trips() {
+        for row in 0..GRID_SIZE as usize {
+            for col in 0..GRID_SIZE as usize {
+                let square = (row, col);
+                assert_eq!(parse_square(&square_name(square)), Some(square));
+            }
+        }
+    }
+
+    #[test]
+    fn squares_are_read_in_either_case() {
+        assert_eq!(parse_square("e2"), Some((6, 4)));
+        assert_eq!(parse_square("E2"), Some((6, 4)));
+        assert_eq!(parse_square("a8"), Some((0, 0)));
+    }
+
+    #[test]
+    fn malformed_squares_are_rejected() {
+        for square in ["", "e", "2", "e0", "e9", "i1", "e22", "e2 ", "2e", "ee"] {
+            assert_eq!(parse_square(square), None, "{:?}", square);
+        }
+    }
+
+    #[test]
+    fn corner_squares_are_named_like_the_engine() {
+        assert_eq!(square_name((0, 0)), "A8");
+        assert_eq!(square_name((7, 7)), "H1");
+        assert_eq!(square_name((6, 4)), "E2");
+    }
+
+    #[test]
+    fn uci_moves_round_trip() {
+        let (from, to) = ((6, 4), (4, 4));
+        assert_eq!(uci_move(from, to), "e2e4");
+        assert_eq!(parse_uci_move(&uci_move(from, to)), Some((from, to)));
+        assert_eq!(parse_uci_move("e7e8q"), Some(((1, 4), (0, 4))));
+    }
+
+    #[test]
+    fn malformed_uci_moves_are_rejected() {
+        for uci in [
+            "", "e2", "e2e", "e2e4e", "e7e8k", "e2e9", "i2e4", "e2e4q5", "é2e4",
+        ] {
+            assert_eq!(parse_uci_move(uci), None, "{:?}", uci);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Crash reports written when the application panics, holding enough recent
+//! state to replay what led up to the crash.
+
+use std::{
+    collections::VecDeque,
+    fs, panic,
+    sync::{Arc, Mutex},
+};
+
+const CRASH_REPORT_PATH: &str = "crash-report.txt";
+/// Number of recent clicks kept for the report.
+const CLICK_HISTORY: usize = 20;
+
+#[derive(Debug, Default)]
+struct CrashContext {
+    clicks: VecDeque<String>,
+    fen: String,
+    board: String,
+}
+
+/// Shared handle that the application updates and the panic hook reads.
+#[derive(Debug, Clone, Default)]
+pub struct CrashReporter {
+    context: Arc<Mutex<CrashContext>>,
+}
+
+impl CrashReporter {
+    /// Installs a panic hook that writes a report before the default hook runs.
+    pub fn install() -> CrashReporter {
+        let reporter = CrashReporter::default();
+        let context = Arc::clone(&reporter.context);
+        let default_hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            // the panic may have struck while the context was locked
+            let report = match context.try_lock() {
+                Ok(context) => format!(
+                    "{}\n\nFEN: {}\n\nBoard:\n{}\n\nLast clicks, oldest first:\n{}\n",
+                    info,
+                    context.fen,
+                    context.board,
+                    Vec::from(context.clicks.clone()).join("\n")
+                ),
+                Err(_) => format!("{}\n\n(application state unavailable)\n", info),
+            };
+            match fs::write(CRASH_REPORT_PATH, report) {
+                Ok(()) => eprintln!("Crash report written to {}", CRASH_REPORT_PATH),
+                Err(err) => eprintln!("Could not write {}: {}", CRASH_REPORT_PATH, err),
+            }
+            default_hook(info);
+        }));
+
+        reporter
+    }
+
+    /// Remembers a click, forgetting the oldest beyond `CLICK_HISTORY`.
+    pub fn record_click(&self, click: String) {
+        if let Ok(mut context) = self.context.lock() {
+            if context.clicks.len() == CLICK_HISTORY {
+                context.clicks.pop_front();
+            }
+            context.clicks.push_back(click);
+        }
+    }
+
+    /// Remembers the position on the board, as FEN and as the engine's board string.
+    pub fn record_position(&self, fen: String, board: String) {
+        if let Ok(mut context) = self.context.lock() {
+            context.fen = fen;
+            context.board = board;
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Forsyth–Edwards Notation export and import.
+
+use crate::{
+    coords::parse_square, empty_board, read_board, role_letter, Board, Move, Piece, BISHOP,
+    GRID_SIZE, KING, KNIGHT, PAWN, QUEEN, ROOK,
+};
+use jblomlof_chess::Game;
+
+/// Full FEN of the position reached by playing `moves` from the starting position.
+pub fn from_moves(moves: &[Move]) -> String {
+    let mut game = Game::new();
+    for mv in moves {
+        mv.play(&mut game);
+    }
+
+    let board = read_board(&game);
+    format!(
+        "{} {} {} {} {} {}",
+        placement(&board),
+        if game.is_white_turn() { "w" } else { "b" },
+        castling_rights(moves),
+        en_passant_target(&board, moves),
+        halfmove_clock(moves),
+        moves.len() / 2 + 1
+    )
+}
+
+/// Half-moves played since the last pawn move or capture, for the fifty-move rule.
+pub fn halfmove_clock(moves: &[Move]) -> usize {
+    let mut game = Game::new();
+    let mut halfmove_clock = 0;
+
+    for mv in moves {
+        let before = read_board(&game);
+        // pawn moves and captures restart the fifty-move count
+        let resets_clock = match (parse_square(&mv.from), parse_square(&mv.to)) {
+            (Some(from_sq), Some(to_sq)) => {
+                before[from_sq.0][from_sq.1].is_some_and(|piece| piece.role == PAWN)
+                    || before[to_sq.0][to_sq.1].is_some()
+            }
+            _ => false,
+        };
+        mv.play(&mut game);
+        halfmove_clock = if resets_clock { 0 } else { halfmove_clock + 1 };
+    }
+
+    halfmove_clock
+}
+
+/// The position before the first move and after each of `moves`, as the FEN
+/// fields that decide whether two positions are the same for repetitions:
+/// placement, side to move, castling rights and en passant square.
+pub fn position_keys(moves: &[Move]) -> Vec<String> {
+    let mut game = Game::new();
+    let mut keys = Vec::new();
+
+    for ply in 0..=moves.len() {
+        if ply > 0 {
+            moves[ply - 1].play(&mut game);
+        }
+        let board = read_board(&game);
+        keys.push(format!(
+            "{} {} {} {}",
+            placement(&board),
+            if game.is_white_turn() { "w" } else { "b" },
+            castling_rights(&moves[..ply]),
+            en_passant_target(&board, &moves[..ply])
+        ));
+    }
+
+    keys
+}
+
+/// Reads the piece placement and side to move of a FEN. The remaining fields
+/// are allowed but not checked, since the board can't make use of them.
+pub fn parse(fen: &str) -> Result<(Board, bool), String> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next().ok_or("The FEN is empty")?;
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != GRID_SIZE as usize {
+        return Err(format!("Expected 8 ranks, found {}", ranks.len()));
+    }
+
+    let mut board = empty_board(GRID_SIZE as usize);
+    for (row, rank) in ranks.iter().enumerate() {
+        let rank_number = GRID_SIZE as usize - row;
+        let mut col = 0;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10).filter(|empty| (1..=8).contains(empty)) {
+                col += empty as usize;
+                continue;
+            }
+            let role = match c.to_ascii_lowercase() {
+                'k' => KING,
+                'q' => QUEEN,
+                'b' => BISHOP,
+                'n' => KNIGHT,
+                'r' => ROOK,
+                'p' => PAWN,
+                _ => return Err(format!("Unknown piece '{}' on rank {}", c, rank_number)),
+            };
+            if col >= GRID_SIZE as usize {
+                return Err(format!("Rank {} has more than 8 squares", rank_number));
+            }
+            board[row][col] = Some(Piece::new(
+                role,
+                (row as i16, col as i16),
+                c.is_ascii_uppercase(),
+            ));
+            col += 1;
+        }
+        if col != GRID_SIZE as usize {
+            return Err(format!("Rank {} doesn't have 8 squares", rank_number));
+        }
+    }
+
+    let white_to_move = match fields.next() {
+        None | Some("w") => true,
+        Some("b") => false,
+        Some(other) => return Err(format!("Unknown side to move '{}'", other)),
+    };
+
+    Ok((board, white_to_move))
+}
+
+/// Piece placement field: ranks eight to one, runs of empty squares as digits.
+fn placement(board: &Board) -> String {
+    let mut ranks = Vec::new();
+    for row in board.iter() {
+        let mut rank = String::new();
+        let mut empty = 0;
+        for square in row.iter() {
+            match square {
+                Some(piece) => {
+                    if empty > 0 {
+                        rank.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    let letter = role_letter(piece.role);
+                    rank.push(if piece.is_white {
+                        letter
+                    } else {
+                        letter.to_ascii_lowercase()
+                    });
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            rank.push_str(&empty.to_string());
+        }
+        ranks.push(rank);
+    }
+    ranks.join("/")
+}
+
+/// Castling availability. A right is lost as soon as anything moves from or to
+/// the king's or that rook's home square, which also covers rooks being captured.
+fn castling_rights(moves: &[Move]) -> String {
+    let touched = |square: &str| {
+        moves
+            .iter()
+            .any(|mv| mv.from.eq_ignore_ascii_case(square) || mv.to.eq_ignore_ascii_case(square))
+    };
+
+    let mut rights = String::new();
+    for (symbol, king, rook) in [
+        ('K', "E1", "H1"),
+        ('Q', "E1", "A1"),
+        ('k', "E8", "H8"),
+        ('q', "E8", "A8"),
+    ] {
+        if !touched(king) && !touched(rook) {
+            rights.push(symbol);
+        }
+    }
+
+    if rights.is_empty() {
+        "-".to_string()
+    } else {
+        rights
+    }
+}
+
+/// Square skipped by a pawn that just advanced two ranks, or "-".
+fn en_passant_target(board: &Board, moves: &[Move]) -> String {
+    let (from, to) = match moves.last() {
+        Some(mv) => (parse_square(&mv.from), parse_square(&mv.to)),
+        None => return "-".to_string(),
+    };
+    if let (Some(from), Some(to)) = (from, to) {
+        let is_pawn = board[to.0][to.1].is_some_and(|piece| piece.role == PAWN);
+        if is_pawn && from.1 == to.1 && (from.0 as i16 - to.0 as i16).abs() == 2 {
+            let skipped_row = (from.0 + to.0) / 2;
+            return format!(
+                "{}{}",
+                (b'a' + from.1 as u8) as char,
+                GRID_SIZE as usize - skipped_row
+            );
+        }
+    }
+    "-".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(list: &[(&str, &str)]) -> Vec<Move> {
+        list.iter()
+            .map(|(from, to)| Move::new(from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    /// (role, is_white) on every square, since pieces don't compare directly.
+    fn squares(board: &Board) -> Vec<Option<(u8, bool)>> {
+        board
+            .iter()
+            .flatten()
+            .map(|square| square.map(|piece| (piece.role, piece.is_white)))
+            .collect()
+    }
+
+    #[test]
+    fn starting_position() {
+        assert_eq!(
+            from_moves(&[]),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn king_move_loses_both_castling_rights() {
+        let fen = from_moves(&moves(&[("E2", "E4"), ("E7", "E5"), ("E1", "E2")]));
+        assert_eq!(fen.split(' ').nth(2), Some("kq"));
+    }
+
+    #[test]
+    fn rook_move_loses_its_side_only() {
+        let fen = from_moves(&moves(&[
+            ("H2", "H4"),
+            ("A7", "A5"),
+            ("H1", "H3"),
+            ("A8", "A6"),
+        ]));
+        assert_eq!(fen.split(' ').nth(2), Some("Qk"));
+    }
+
+    #[test]
+    fn double_push_sets_en_passant_square() {
+        let fen = from_moves(&moves(&[("E2", "E4")]));
+        assert_eq!(fen.split(' ').nth(3), Some("e3"));
+
+        let fen = from_moves(&moves(&[("E2", "E4"), ("G8", "F6")]));
+        assert_eq!(fen.split(' ').nth(3), Some("-"));
+    }
+
+    #[test]
+    fn halfmove_clock_counts_and_resets() {
+        let knights = moves(&[("G1", "F3"), ("G8", "F6"), ("B1", "C3")]);
+        assert_eq!(halfmove_clock(&knights), 3);
+
+        let mut with_pawn = knights.clone();
+        with_pawn.extend(moves(&[("E7", "E5")]));
+        assert_eq!(halfmove_clock(&with_pawn), 0);
+    }
+
+    #[test]
+    fn exported_position_reads_back() {
+        let played = moves(&[("E2", "E4"), ("C7", "C5"), ("G1", "F3")]);
+        let mut game = Game::new();
+        for mv in &played {
+            mv.play(&mut game);
+        }
+
+        let (board, white_to_move) = parse(&from_moves(&played)).unwrap();
+        assert_eq!(squares(&board), squares(&read_board(&game)));
+        assert_eq!(white_to_move, game.is_white_turn());
+    }
+
+    #[test]
+    fn parse_rejects_malformed_placement() {
+        assert!(parse("").is_err());
+        assert!(parse("8/8/8/8/8/8/8 w").is_err());
+        assert!(parse("9/8/8/8/8/8/8/8 w").is_err());
+        assert!(parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w").is_err());
+        assert!(parse("8/8/8/8/8/8/8/8 x").is_err());
+    }
+}
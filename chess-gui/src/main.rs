@@ -7,27 +7,127 @@ use ggez::graphics::Color;
  */
 use jblomlof_chess::{Game, GameState};
 
+use ggez::audio::{self, SoundSource};
 use ggez::{conf, event, graphics, Context, ContextBuilder, GameError, GameResult};
-use std::{collections::HashMap, path};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-/// A chess board is 8x8 tiles.
+mod attacks;
+mod coords;
+mod crash;
+mod fen;
+mod pgn;
+mod prefs;
+mod save;
+mod spectator;
+mod trainer;
+
+use coords::{parse_square, square_name};
+use crash::CrashReporter;
+use prefs::{CoordinateMode, Preferences};
+use spectator::Spectator;
+use trainer::{Trainer, Verdict};
+
+/// A chess board is 8x8 tiles, the only size the engine plays on.
 const GRID_SIZE: i16 = 8;
-/// Sutible size of each tile.
+/// Sutible size of each tile, unless the CHESS_CELL_SIZE environment variable gives another.
 const GRID_CELL_SIZE: (i16, i16) = (90, 90);
+/// Range of tile sizes CHESS_CELL_SIZE may set.
+const CELL_SIZE_RANGE: std::ops::RangeInclusive<i16> = 30..=400;
+
+/// Width of the panel to the right of the board.
+const SIDE_PANEL_WIDTH: f32 = 240.0;
+/// Size of the pieces listed in the side panel.
+const CAPTURED_PIECE_SIZE: f32 = 32.0;
+/// Room left after each captured piece for its "x2" count.
+const CAPTURE_COUNT_WIDTH: f32 = 32.0;
+/// Height of a line in the side panel's move list.
+const MOVE_LIST_LINE_HEIGHT: f32 = 22.0;
+/// How much one press of + or - changes the sound effect volume.
+const VOLUME_STEP: f32 = 0.1;
+
+/// Size of a board of `board_size` x `board_size` tiles of `cell_size`.
+fn board_extent(board_size: usize, cell_size: (i16, i16)) -> (f32, f32) {
+    (
+        board_size as f32 * cell_size.0 as f32,
+        board_size as f32 * cell_size.1 as f32,
+    )
+}
 
-/// Size of the application window.
-const SCREEN_SIZE: (f32, f32) = (
-    GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32,
-    GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32,
-);
+/// Size of the application window: the board with the side panel beside it.
+fn screen_size(board_size: usize, cell_size: (i16, i16)) -> (f32, f32) {
+    let board = board_extent(board_size, cell_size);
+    (board.0 + SIDE_PANEL_WIDTH, board.1)
+}
 
 // GUI Color representations
-const BLACK: graphics::Color =
-    graphics::Color::new(228.0 / 255.0, 196.0 / 255.0, 108.0 / 255.0, 1.0);
-const WHITE: graphics::Color =
-    graphics::Color::new(188.0 / 255.0, 140.0 / 255.0, 76.0 / 255.0, 1.0);
-const HIGHLIGHT: graphics::Color =
-    graphics::Color::new(40.0 / 255.0, 90.0 / 255.0, 80.0 / 255.0, 0.3);
+/// Colours of the board's tiles.
+#[derive(Clone, Copy)]
+struct Theme {
+    // name the theme is saved under in the preferences
+    name: &'static str,
+    light: graphics::Color,
+    dark: graphics::Color,
+}
+
+/// The board theme picked in the preferences, or the first if it is unknown,
+/// with any tile colours the preferences set in place of its own.
+fn preferred_theme(preferences: &Preferences) -> Theme {
+    let mut theme = *THEMES
+        .iter()
+        .find(|theme| theme.name == preferences.board_theme)
+        .unwrap_or(&THEMES[0]);
+    if let Some(light) = preferences.light_tiles {
+        theme.light = light.into();
+    }
+    if let Some(dark) = preferences.dark_tiles {
+        theme.dark = dark.into();
+    }
+    theme
+}
+
+/// Board themes, in the order T cycles through them.
+const THEMES: [Theme; 3] = [
+    Theme {
+        name: "brown",
+        light: graphics::Color::new(228.0 / 255.0, 196.0 / 255.0, 108.0 / 255.0, 1.0),
+        dark: graphics::Color::new(188.0 / 255.0, 140.0 / 255.0, 76.0 / 255.0, 1.0),
+    },
+    Theme {
+        name: "green",
+        light: graphics::Color::new(238.0 / 255.0, 238.0 / 255.0, 210.0 / 255.0, 1.0),
+        dark: graphics::Color::new(118.0 / 255.0, 150.0 / 255.0, 86.0 / 255.0, 1.0),
+    },
+    Theme {
+        name: "blue",
+        light: graphics::Color::new(222.0 / 255.0, 227.0 / 255.0, 230.0 / 255.0, 1.0),
+        dark: graphics::Color::new(140.0 / 255.0, 162.0 / 255.0, 173.0 / 255.0, 1.0),
+    },
+];
+const PROMOTION_MARKER: graphics::Color =
+    graphics::Color::new(200.0 / 255.0, 40.0 / 255.0, 60.0 / 255.0, 1.0);
+const PREMOVE_ARROW: graphics::Color =
+    graphics::Color::new(70.0 / 255.0, 90.0 / 255.0, 200.0 / 255.0, 0.5);
+const ONLY_MOVE_ARROW: graphics::Color =
+    graphics::Color::new(40.0 / 255.0, 140.0 / 255.0, 90.0 / 255.0, 0.7);
+const CHECKMATE_MARKER: graphics::Color = graphics::Color::new(200.0 / 255.0, 0.0, 0.0, 0.9);
+const KEYBOARD_CURSOR: graphics::Color =
+    graphics::Color::new(250.0 / 255.0, 210.0 / 255.0, 40.0 / 255.0, 1.0);
+const ERROR_FLASH: graphics::Color =
+    graphics::Color::new(200.0 / 255.0, 30.0 / 255.0, 30.0 / 255.0, 1.0);
+const ANNOTATION_ARROW: graphics::Color =
+    graphics::Color::new(210.0 / 255.0, 80.0 / 255.0, 30.0 / 255.0, 0.8);
+const ANNOTATION_MARKER: graphics::Color =
+    graphics::Color::new(210.0 / 255.0, 80.0 / 255.0, 30.0 / 255.0, 0.45);
+const DEFENDED_DOT: graphics::Color =
+    graphics::Color::new(40.0 / 255.0, 170.0 / 255.0, 60.0 / 255.0, 1.0);
+const ATTACKED_DOT: graphics::Color =
+    graphics::Color::new(210.0 / 255.0, 40.0 / 255.0, 40.0 / 255.0, 1.0);
 
 pub const NONE: u8 = 0;
 pub const KING: u8 = 1;
@@ -37,6 +137,29 @@ pub const KNIGHT: u8 = 4;
 pub const ROOK: u8 = 5;
 pub const PAWN: u8 = 6;
 
+/// File the current position is exported to.
+const FEN_EXPORT_PATH: &str = "position.fen";
+/// Sprite theme whose images sit directly in the resources directory.
+const DEFAULT_SPRITE_THEME: &str = "default";
+/// File a game in progress is saved to and resumed from.
+const SAVE_PATH: &str = "game.json";
+/// How long a toast message stays on screen.
+const TOAST_DURATION: Duration = Duration::from_secs(2);
+/// How long after a first press of R a second one resigns the game.
+const RESIGN_CONFIRM_WINDOW: Duration = Duration::from_secs(2);
+/// How long the computer opponent waits before moving.
+const AI_MOVE_DELAY: Duration = Duration::from_millis(600);
+/// How long a moved piece takes to slide to its square at normal animation speed.
+const SLIDE_DURATION: Duration = Duration::from_millis(150);
+/// How long a capture flash takes to fade out at normal animation speed.
+const CAPTURE_FLASH_DURATION: Duration = Duration::from_millis(150);
+/// How long the flash on a square a piece can't move to lasts.
+const ERROR_FLASH_DURATION: Duration = Duration::from_millis(300);
+/// How long the highlights of a newly selected piece take to fade in at normal animation speed.
+const SELECTION_FADE_DURATION: Duration = Duration::from_millis(100);
+/// How long to rest between frames when there is nothing new to draw.
+const IDLE_FRAME_SLEEP: Duration = Duration::from_millis(10);
+
 #[derive(Debug, Copy, Clone)]
 struct Piece {
     role: u8,
@@ -54,319 +177,3735 @@ impl Piece {
     }
 }
 
+/// A move between two engine squares ("E7", "E8"), with the role a promoting
+/// pawn becomes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Move {
+    from: String,
+    to: String,
+    // None for moves that don't promote
+    #[serde(default)]
+    promotion: Option<u8>,
+}
+
+impl Move {
+    fn new(from: String, to: String) -> Self {
+        Move {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+
+    /// Plays the move, first telling the engine which piece a promoting pawn becomes.
+    fn play(&self, game: &mut Game) {
+        if let Some(role) = self.promotion {
+            game.set_promotion(role_letter(role).to_string());
+        }
+        game.make_move(&self.from, &self.to);
+    }
+}
+
+/// Pieces offered when a pawn promotes, in the order the dialog shows them.
+const PROMOTION_CHOICES: [u8; 4] = [QUEEN, ROOK, BISHOP, KNIGHT];
+
+/// Piece placement indexed as `board[row][column]`, row 0 being the eighth rank.
+type Board = Vec<Vec<Option<Piece>>>;
+/// (row, column) squares a piece moves from and to.
+type Step = ((usize, usize), (usize, usize));
+/// Piece images of a sprite theme by (is_white, role).
+type Sprites = HashMap<(bool, u8), graphics::Image>;
+
+/// A board of `size` x `size` empty squares.
+fn empty_board(size: usize) -> Board {
+    vec![vec![None; size]; size]
+}
+
+/// Upper-case letter for a piece role, as used in FEN and algebraic notation.
+fn role_letter(role: u8) -> char {
+    match role {
+        KING => 'K',
+        QUEEN => 'Q',
+        BISHOP => 'B',
+        KNIGHT => 'N',
+        ROOK => 'R',
+        _ => 'P',
+    }
+}
+
+/// Lower-case English name of a piece role.
+fn role_name(role: u8) -> &'static str {
+    match role {
+        KING => "king",
+        QUEEN => "queen",
+        BISHOP => "bishop",
+        KNIGHT => "knight",
+        ROOK => "rook",
+        _ => "pawn",
+    }
+}
+
+/// Every occupied square on the board with the piece standing there.
+fn pieces(board: &Board) -> impl Iterator<Item = ((usize, usize), Piece)> + '_ {
+    board.iter().enumerate().flat_map(|(row, rank)| {
+        rank.iter()
+            .enumerate()
+            .filter_map(move |(col, square)| square.map(|piece| ((row, col), piece)))
+    })
+}
+
+/// Conventional material value of a piece role, with the king counted as nothing.
+fn role_value(role: u8) -> u32 {
+    match role {
+        QUEEN => 9,
+        ROOK => 5,
+        BISHOP | KNIGHT => 3,
+        PAWN => 1,
+        _ => 0,
+    }
+}
+
+/// Total material value of one side's pieces.
+fn material(board: &Board, is_white: bool) -> u32 {
+    pieces(board)
+        .filter(|(_, piece)| piece.is_white == is_white)
+        .map(|(_, piece)| role_value(piece.role))
+        .sum()
+}
+
+/// Whether neither side has the material left to checkmate: bare kings, a
+/// single bishop or knight, or only bishops standing on squares of one colour.
+fn insufficient_material(board: &Board) -> bool {
+    let officers: Vec<((usize, usize), Piece)> = pieces(board)
+        .filter(|(_, piece)| piece.role != KING)
+        .collect();
+    match officers.as_slice() {
+        [] => true,
+        [(_, piece)] => piece.role == BISHOP || piece.role == KNIGHT,
+        [((row, col), _), ..] => officers
+            .iter()
+            .all(|((r, c), piece)| piece.role == BISHOP && (r + c) % 2 == (row + col) % 2),
+    }
+}
+
+/// Which side is ahead on material and by how much, such as "White +3".
+fn material_balance(board: &Board) -> String {
+    let white = material(board, true);
+    let black = material(board, false);
+    match white.cmp(&black) {
+        std::cmp::Ordering::Greater => format!("White +{}", white - black),
+        std::cmp::Ordering::Less => format!("Black +{}", black - white),
+        std::cmp::Ordering::Equal => "Material is even".to_string(),
+    }
+}
+
+/// Rough stage of a game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl GamePhase {
+    /// Classifies a position by the material left beside the pawns and by
+    /// how far the game has gone.
+    fn of(board: &Board, fullmove: usize) -> GamePhase {
+        let pawns = pieces(board)
+            .filter(|(_, piece)| piece.role == PAWN)
+            .count() as u32;
+        // 62 at the start: two queens, four rooks, four bishops and four knights
+        let officers = material(board, true) + material(board, false) - pawns;
+
+        if officers <= 26 {
+            GamePhase::Endgame
+        } else if fullmove <= 10 && officers >= 50 {
+            GamePhase::Opening
+        } else {
+            GamePhase::Middlegame
+        }
+    }
+}
+
+/// Reads the engine's board into piece placements.
+fn read_board(game: &Game) -> Board {
+    parse_board(&Game::get_board(game))
+}
+
+/// Parses a board string in the engine's format into piece placements.
+fn parse_board(board_str: &str) -> Board {
+    let mut board = empty_board(GRID_SIZE as usize);
+
+    // one line per rank, eighth rank first; surrounding whitespace is ignored
+    let ranks = board_str
+        .lines()
+        .map(str::trim)
+        .filter(|rank| !rank.is_empty());
+    for (i, rank) in ranks.take(GRID_SIZE as usize).enumerate() {
+        for (j, piece) in rank.chars().take(GRID_SIZE as usize).enumerate() {
+            if piece != '*' {
+                let is_white = piece.is_uppercase();
+                let role = match piece.to_ascii_lowercase() {
+                    'k' => KING,
+                    'q' => QUEEN,
+                    'b' => BISHOP,
+                    'n' => KNIGHT,
+                    'r' => ROOK,
+                    'p' => PAWN,
+                    _ => NONE, // Should never happen
+                };
+                board[i][j] = Some(Piece::new(role, (i as i16, j as i16), is_white));
+            }
+        }
+    }
+
+    board
+}
+
+/// Takes the clock history back to `plies` moves and returns the (white, black)
+/// clock times saved when the first move taken back was played.
+fn rewind_clocks(
+    clock_history: &mut Vec<(Duration, Duration)>,
+    plies: usize,
+) -> Option<(Duration, Duration)> {
+    let clocks = clock_history.get(plies).copied();
+    clock_history.truncate(plies);
+    clocks
+}
+
+/// Follows a move from `from` to `to` on `board`, read before the move is
+/// played, in the set of squares holding promoted pieces.
+fn track_promoted(
+    promoted: &mut HashSet<(usize, usize)>,
+    board: &Board,
+    from: (usize, usize),
+    to: (usize, usize),
+) {
+    let promotes = board[from.0][from.1]
+        .is_some_and(|piece| piece.role == PAWN && (to.0 == 0 || to.0 == GRID_SIZE as usize - 1));
+
+    // a capture removes whatever stood on the destination
+    promoted.remove(&to);
+    if promoted.remove(&from) || promotes {
+        promoted.insert(to);
+    }
+}
+
+/// Replays a game and returns the (row, column) squares holding promoted pieces.
+fn promoted_squares(moves: &[Move]) -> HashSet<(usize, usize)> {
+    let mut game = Game::new();
+    let mut promoted = HashSet::new();
+
+    for mv in moves {
+        let (Some(from_sq), Some(to_sq)) = (parse_square(&mv.from), parse_square(&mv.to)) else {
+            continue;
+        };
+        track_promoted(&mut promoted, &read_board(&game), from_sq, to_sq);
+        mv.play(&mut game);
+    }
+
+    promoted
+}
+
+/// The piece a move from `from` to `to` takes on `board`, read before the move is played.
+fn taken_piece(board: &Board, from: (usize, usize), to: (usize, usize)) -> Option<Piece> {
+    let is_pawn = board[from.0][from.1].is_some_and(|piece| piece.role == PAWN);
+    match board[to.0][to.1] {
+        Some(piece) => Some(piece),
+        // a pawn changing file onto an empty square takes en passant
+        None if is_pawn && from.1 != to.1 => board[from.0][to.1],
+        None => None,
+    }
+}
+
+/// Adds a taken piece to the tally of captures by (is_white, role).
+fn count_capture(counts: &mut HashMap<(bool, u8), usize>, piece: Piece) {
+    *counts.entry((piece.is_white, piece.role)).or_insert(0) += 1;
+}
+
+/// Replays a game and returns how many pieces of each (is_white, role) were captured in it.
+fn capture_counts(moves: &[Move]) -> HashMap<(bool, u8), usize> {
+    let mut game = Game::new();
+    let mut counts = HashMap::new();
+
+    for mv in moves {
+        let (Some(from_sq), Some(to_sq)) = (parse_square(&mv.from), parse_square(&mv.to)) else {
+            continue;
+        };
+        if let Some(piece) = taken_piece(&read_board(&game), from_sq, to_sq) {
+            count_capture(&mut counts, piece);
+        }
+        mv.play(&mut game);
+    }
+
+    counts
+}
+
+/// Maps a mouse position in window pixels to the screen coordinates everything
+/// is drawn in, which stop matching once the window has been resized.
+fn window_to_screen(ctx: &Context, x: f32, y: f32) -> [f32; 2] {
+    let view = graphics::screen_coordinates(ctx);
+    let (width, height) = graphics::drawable_size(ctx);
+    [view.x + x * view.w / width, view.y + y * view.h / height]
+}
+
+/// Time on a clock as minutes and seconds, "9:05".
+fn clock_face(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Plays a sound effect at `volume` if it was loaded, reporting rather than failing on errors.
+fn play_sound(sound: &mut Option<audio::Source>, volume: f32) {
+    if volume <= 0.0 {
+        return;
+    }
+    if let Some(sound) = sound {
+        sound.set_volume(volume);
+        if let Err(err) = sound.play_detached() {
+            eprintln!("Could not play sound: {}", err);
+        }
+    }
+}
+
+/// Whether the piece on a hovered square is defended and attacked, worked out
+/// for one square of one position.
+#[derive(Debug, Clone, Copy)]
+struct HoverThreats {
+    square: (usize, usize),
+    ply: usize,
+    defended: bool,
+    attacked: bool,
+}
+
+/// Settings taken from the command line.
+#[derive(Debug)]
+struct LaunchOptions {
+    // port to serve the current position on, from `--serve <port>`
+    serve_port: Option<u16>,
+    // address to serve it on, from `--serve-host <address>`; only this
+    // machine can reach it unless another address is given
+    serve_host: String,
+    // tiles along each side of the board, from `--board-size <n>`
+    board_size: usize,
+    // width and height of a tile, from the CHESS_CELL_SIZE environment variable
+    cell_size: (i16, i16),
+    // PGN file with an opening line to drill, from `--train <file>`
+    train_path: Option<String>,
+    // side the player takes in the trainer, from `--side <white|black>`
+    train_as_white: bool,
+    // position to open on, from `--fen <fen>`
+    fen: Option<String>,
+    // PGN file with a game to step through, from `--pgn <file>`
+    pgn_path: Option<String>,
+}
+
+impl LaunchOptions {
+    fn from_args() -> LaunchOptions {
+        let mut options = LaunchOptions {
+            serve_port: None,
+            serve_host: "127.0.0.1".to_string(),
+            board_size: GRID_SIZE as usize,
+            cell_size: LaunchOptions::cell_size_from_env(),
+            train_path: None,
+            train_as_white: true,
+            fen: None,
+            pgn_path: None,
+        };
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--serve" => match args.next().and_then(|port| port.parse().ok()) {
+                    Some(port) => options.serve_port = Some(port),
+                    None => eprintln!("--serve expects a port number"),
+                },
+                "--serve-host" => match args.next() {
+                    Some(host) => options.serve_host = host,
+                    None => eprintln!("--serve-host expects an address"),
+                },
+                // the engine only plays on 8x8, so other sizes are for variant work only
+                "--board-size" if cfg!(feature = "experimental-board-sizes") => {
+                    match args.next().and_then(|size| size.parse().ok()) {
+                        // the engine's pieces and moves stay on its 8x8 corner, so
+                        // boards smaller than that would leave them off the edge
+                        Some(size @ 8..=26) => options.board_size = size,
+                        _ => eprintln!("--board-size expects a size from 8 to 26"),
+                    }
+                }
+                "--board-size" => {
+                    eprintln!("--board-size needs the experimental-board-sizes feature")
+                }
+                "--train" => match args.next() {
+                    Some(path) => options.train_path = Some(path),
+                    None => eprintln!("--train expects a PGN file"),
+                },
+                "--side" => match args.next().as_deref() {
+                    Some("white") => options.train_as_white = true,
+                    Some("black") => options.train_as_white = false,
+                    _ => eprintln!("--side expects white or black"),
+                },
+                "--fen" => match args.next() {
+                    Some(fen) => options.fen = Some(fen),
+                    None => eprintln!("--fen expects a position"),
+                },
+                "--pgn" => match args.next() {
+                    Some(path) => options.pgn_path = Some(path),
+                    None => eprintln!("--pgn expects a PGN file"),
+                },
+                _ => eprintln!("Ignoring unknown argument {}", arg),
+            }
+        }
+
+        options
+    }
+
+    /// Tile size given by CHESS_CELL_SIZE, for larger boards on high resolution
+    /// screens, or the default when it is unset or not a size in range.
+    fn cell_size_from_env() -> (i16, i16) {
+        let value = match std::env::var("CHESS_CELL_SIZE") {
+            Ok(value) => value,
+            Err(_) => return GRID_CELL_SIZE,
+        };
+        match value.trim().parse() {
+            Ok(size) if CELL_SIZE_RANGE.contains(&size) => (size, size),
+            _ => {
+                eprintln!(
+                    "CHESS_CELL_SIZE must be a size from {} to {}, using {}",
+                    CELL_SIZE_RANGE.start(),
+                    CELL_SIZE_RANGE.end(),
+                    GRID_CELL_SIZE.0
+                );
+                GRID_CELL_SIZE
+            }
+        }
+    }
+}
+
 /// GUI logic and event implementation structure.
 struct AppState {
+    // piece images of the default sprite theme, which any other theme falls back on
     sprites: HashMap<(bool, u8), graphics::Image>,
+    // piece images of the sprite theme picked in the preferences, if not the default
+    theme_sprites: HashMap<(bool, u8), graphics::Image>,
+    // sprite themes found under the resources directory, the default first
+    sprite_themes: Vec<String>,
+    // sprite theme being previewed and its piece images, until it is applied or cancelled
+    sprite_preview: Option<(String, Sprites)>,
+    // checkerboard drawn under the highlights, and the theme it was built for
+    board_mesh: (&'static str, graphics::Mesh),
+    // something on screen has changed since the last frame was drawn
+    dirty: bool,
+    // when the current game began, and how long it lasted once it is over
+    game_started: Instant,
+    game_length: Option<Duration>,
+    // sound effects for each kind of move, if they could be loaded
+    move_sound: Option<audio::Source>,
+    capture_sound: Option<audio::Source>,
+    castle_sound: Option<audio::Source>,
+    check_sound: Option<audio::Source>,
+    checkmate_sound: Option<audio::Source>,
+    draw_sound: Option<audio::Source>,
     // Example board representation.
-    board: [[Option<Piece>; 8]; 8],
+    board: Board,
+    // tiles along each side of the board; only 8 unless built with the
+    // experimental-board-sizes feature
+    board_size: usize,
+    // width and height of a tile on screen
+    cell_size: (i16, i16),
     // Imported game representation.
     game: Game,
-    // places to highlight
+    // (row, column) places to highlight
     highlight_poses: Vec<(usize, usize)>,
     // which piece is being choosed
     highlight_piece: Option<Piece>,
+    // whether the selected piece's legal moves are marked on the board; they
+    // stay playable either way
+    show_hints: bool,
+    // developer overlay showing tile bounds and coordinates (debug builds only)
+    debug_grid: bool,
+    // moves played this game, as (from, to) file-rank pairs
+    move_history: Vec<Move>,
+    // user preferences, loaded from disk at startup
+    preferences: Preferences,
+    // short-lived message shown at the bottom of the window
+    toast: Option<(String, Instant)>,
+    // text typed after ':', while the command line is open
+    command_buffer: Option<String>,
+    // why the last submitted command was refused, shown beside it
+    command_error: Option<String>,
+    // board drawn with black at the bottom
+    flipped: bool,
+    // board turned around fullscreen for the opponent to look at
+    presenting: bool,
+    // window filling the screen at the player's request, apart from presenting
+    fullscreen: bool,
+    // the next frame drawn is to be saved as a screenshot
+    screenshot_requested: bool,
+    // position of the selected piece and when it was selected, for fading in its highlights
+    selection_fade: Option<((i16, i16), Instant)>,
+    // square of the latest capture and when it happened, for the fading flash
+    capture_flash: Option<((usize, usize), Instant)>,
+    // square a piece was just refused a move to and when, for the red flash
+    error_flash: Option<((usize, usize), Instant)>,
+    // (row, column) squares the last moved pieces slide between, two of them when
+    // castling, and when they set off
+    slide: Option<(Vec<Step>, Instant)>,
+    // ply being looked back at and the position after it while stepping through
+    // the moves played; None shows the live game
+    review: Option<(usize, Board)>,
+    // how often each position has come up this game, and the key of the current one
+    repetitions: HashMap<String, u32>,
+    position_key: String,
+    // square the secondary button went down on, for drawing an annotation
+    annotation_start: Option<(usize, usize)>,
+    // arrows and marked squares drawn on the board for teaching
+    annotations: Vec<Step>,
+    marked_squares: HashSet<(usize, usize)>,
+    // optional HTTP endpoint publishing the position to spectators
+    spectator: Option<Spectator>,
+    // (row, column) squares holding pieces that came from a promotion
+    promoted: HashSet<(usize, usize)>,
+    // how many pieces of each (is_white, role) have been taken this game
+    captured: HashMap<(bool, u8), usize>,
+    // moves played this game in algebraic notation, for the move list
+    move_log: Vec<String>,
+    // how many lines the move list is scrolled back from its latest move
+    move_log_scroll: usize,
+    // opening line being drilled, if training
+    trainer: Option<Trainer>,
+    // role and index among its movable pieces last picked by quick-select
+    quick_select: Option<(u8, usize)>,
+    // (row, column) square under the mouse, if on the board
+    hovered: Option<(usize, usize)>,
+    // mouse position in screen coordinates
+    cursor: [f32; 2],
+    // (row, column) square of the piece being dragged
+    dragging: Option<(usize, usize)>,
+    // (row, column) square under the keyboard cursor, once Enter has shown it
+    keyboard_cursor: Option<(usize, usize)>,
+    // threats to the hovered piece, kept until the hover or position changes
+    hover_threats: Option<HoverThreats>,
+    // (row, column) the selected pawn is promoting on, while the dialog is open
+    pending_promotion: Option<(usize, usize)>,
+    // previewed destination (row, column) waiting for a confirming click
+    pending_move: Option<(usize, usize)>,
+    // recent clicks and position for the panic hook's crash report
+    crash_reporter: CrashReporter,
+    // when R was first pressed, while R again or Y resigns and N cancels
+    pending_resign: Option<Instant>,
+    // time left on each side's clock
+    white_time: Duration,
+    black_time: Duration,
+    // (white, black) time left when each move of `move_history` was played
+    clock_history: Vec<(Duration, Duration)>,
+    // why the game ended, shown over the board until a new game is started
+    game_over: Option<String>,
+    // title last given to the window, so it is only changed when it differs
+    window_title: String,
+    // computer opponent playing random legal moves, and the side it plays
+    ai_enabled: bool,
+    ai_plays_black: bool,
+    // when the computer opponent makes its pending move
+    ai_move_due: Option<Instant>,
+    // (row, column) of the piece picked for a premove while the computer thinks
+    premove_from: Option<(usize, usize)>,
+    // move queued to be played as soon as the computer opponent has replied
+    premove: Option<Move>,
+    // position loaded from a FEN, with the FEN itself. The engine can only
+    // start from the initial position, so this is shown for study only
+    fixed_position: Option<(Board, String)>,
 }
 
 impl AppState {
     /// Initialise new application, i.e. initialise new game and load resources.
-    fn new(ctx: &mut Context) -> GameResult<AppState> {
-        let state = AppState {
-            sprites: AppState::load_sprites(ctx),
-            board: [[None; 8]; 8],
+    fn new(
+        ctx: &mut Context,
+        options: &LaunchOptions,
+        crash_reporter: CrashReporter,
+    ) -> GameResult<AppState> {
+        let preferences = Preferences::load();
+        let sprites = AppState::load_sprites(ctx, DEFAULT_SPRITE_THEME);
+        let theme_sprites = AppState::load_theme_sprites(ctx, &preferences.sprite_theme);
+        let sprite_themes = AppState::find_sprite_themes(ctx);
+        let board_theme = preferred_theme(&preferences);
+        let board_mesh =
+            AppState::build_board_mesh(ctx, options.board_size, options.cell_size, &board_theme)?;
+        let clock_time = preferences.clock_time();
+        let mut state = AppState {
+            board_mesh: (board_theme.name, board_mesh),
+            dirty: true,
+            game_started: Instant::now(),
+            game_length: None,
+            move_sound: AppState::load_sound(ctx, "/move.wav"),
+            capture_sound: AppState::load_sound(ctx, "/capture.wav"),
+            castle_sound: AppState::load_sound(ctx, "/castle.wav"),
+            check_sound: AppState::load_sound(ctx, "/check.wav"),
+            checkmate_sound: AppState::load_sound(ctx, "/checkmate.wav"),
+            draw_sound: AppState::load_sound(ctx, "/draw.wav"),
+            trainer: None,
+            sprites,
+            theme_sprites,
+            sprite_themes,
+            sprite_preview: None,
+            board: empty_board(options.board_size),
+            board_size: options.board_size,
+            cell_size: options.cell_size,
             game: Game::new(),
             highlight_poses: Vec::new(),
+            show_hints: true,
             highlight_piece: None,
+            debug_grid: false,
+            move_history: Vec::new(),
+            preferences,
+            toast: None,
+            command_buffer: None,
+            command_error: None,
+            flipped: false,
+            presenting: false,
+            fullscreen: false,
+            screenshot_requested: false,
+            selection_fade: None,
+            capture_flash: None,
+            error_flash: None,
+            slide: None,
+            review: None,
+            repetitions: HashMap::new(),
+            position_key: String::new(),
+            annotation_start: None,
+            annotations: Vec::new(),
+            marked_squares: HashSet::new(),
+            spectator: None,
+            promoted: HashSet::new(),
+            captured: HashMap::new(),
+            move_log: Vec::new(),
+            move_log_scroll: 0,
+            quick_select: None,
+            hovered: None,
+            cursor: [0.0, 0.0],
+            dragging: None,
+            keyboard_cursor: None,
+            hover_threats: None,
+            pending_promotion: None,
+            pending_move: None,
+            crash_reporter,
+            pending_resign: None,
+            white_time: clock_time,
+            black_time: clock_time,
+            clock_history: Vec::new(),
+            game_over: None,
+            window_title: "Schack".to_string(),
+            ai_enabled: false,
+            ai_plays_black: true,
+            ai_move_due: None,
+            premove_from: None,
+            premove: None,
+            fixed_position: None,
         };
 
+        if let Some(port) = options.serve_port {
+            state.spectator = Spectator::start(&options.serve_host, port, fen::from_moves(&[]));
+            if state.spectator.is_none() {
+                state.show_toast(format!(
+                    "{}:{} is unavailable, not serving the position",
+                    options.serve_host, port
+                ));
+            }
+        }
+        if let Some(path) = &options.train_path {
+            match Trainer::load(path, options.train_as_white) {
+                Ok(trainer) => {
+                    state.trainer = Some(trainer);
+                    state.new_game();
+                }
+                Err(err) => state.show_toast(format!("Could not load the opening line: {}", err)),
+            }
+        }
+        // an imported game opens on its first position, ready to be stepped through
+        if let Some(path) = &options.pgn_path {
+            match std::fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| pgn::parse(&text))
+            {
+                Ok(moves) => {
+                    state.trainer = None;
+                    state.show_toast(format!(
+                        "Loaded game of {} moves, use Left and Right to step through it",
+                        moves.len()
+                    ));
+                    state.replace_moves(moves);
+                    state.review_at(0);
+                }
+                Err(err) => state.show_toast(format!("Could not load {}: {}", path, err)),
+            }
+        }
+        // the engine always starts from the initial position, so a FEN position
+        // is shown view only, the same as one pasted from the clipboard
+        if let Some(fen) = &options.fen {
+            match fen::parse(fen) {
+                Ok((board, _)) => {
+                    state.trainer = None;
+                    state.fixed_position = Some((board, fen.trim().to_string()));
+                }
+                Err(err) => eprintln!("Invalid FEN, starting from the initial position: {}", err),
+            }
+        }
+        state.publish_position();
+
         Ok(state)
     }
 
     fn load_board(&mut self) -> () {
-        let board_str: String = Game::get_board(&self.game);
-
-        for i in 0..8 {
-            for j in 0..8 {
-                let piece = board_str.chars().nth(i * 8 + (j + i)).unwrap(); // + 2i to skip "\n"
-
-                if piece != '*' {
-                    let is_white = piece.is_uppercase();
-                    let role = match piece.to_ascii_lowercase() {
-                        'k' => KING,
-                        'q' => QUEEN,
-                        'b' => BISHOP,
-                        'n' => KNIGHT,
-                        'r' => ROOK,
-                        'p' => PAWN,
-                        _ => NONE, // Should never happen
-                    };
-                    self.board[i][j] = Some(Piece::new(role, (i as i16, j as i16), is_white));
-                } else {
-                    self.board[i][j] = None;
-                }
+        let position = match (&self.review, &self.fixed_position) {
+            (Some((_, board)), _) | (None, Some((board, _))) => board.clone(),
+            (None, None) => read_board(&self.game),
+        };
+        // the engine's 8x8 position sits in the top left of larger boards
+        self.board = empty_board(self.board_size);
+        for ((row, col), piece) in pieces(&position) {
+            if row < self.board_size && col < self.board_size {
+                self.board[row][col] = Some(piece);
             }
         }
     }
 
-    fn to_file_rank(&self, _column: usize, _row: usize) -> String {
-        let files: [&str; 8] = ["A", "B", "C", "D", "E", "F", "G", "H"];
-        let rank: String = (7 - _column + 1).to_string();
-        let mut file: String = files[_row].to_string();
-
-        file.push_str(&rank);
-        file
+    /// Throws away the current game and starts over from the initial position.
+    fn new_game(&mut self) {
+        self.game = Game::new();
+        self.board = empty_board(self.board_size);
+        self.highlight_poses = Vec::new();
+        self.highlight_piece = None;
+        self.pending_move = None;
+        self.pending_promotion = None;
+        self.quick_select = None;
+        self.hover_threats = None;
+        self.capture_flash = None;
+        self.error_flash = None;
+        self.slide = None;
+        self.review = None;
+        self.dragging = None;
+        self.keyboard_cursor = None;
+        self.pending_resign = None;
+        self.move_history = Vec::new();
+        self.white_time = self.preferences.clock_time();
+        self.black_time = self.preferences.clock_time();
+        self.clock_history = Vec::new();
+        self.game_over = None;
+        self.game_started = Instant::now();
+        self.game_length = None;
+        self.ai_move_due = None;
+        self.premove_from = None;
+        self.premove = None;
+        self.promoted = HashSet::new();
+        self.captured = HashMap::new();
+        self.move_log = Vec::new();
+        self.count_positions();
+        self.move_log_scroll = 0;
+        self.clear_annotations();
+        self.fixed_position = None;
+        self.publish_position();
+        self.play_book_moves();
     }
 
-    fn to_row_column(&self, filerank: &str) -> (usize, usize) {
-        let chars: Vec<char> = String::from(filerank).chars().collect::<Vec<char>>();
-
-        let column: usize = match chars[0] {
-            'A' => 0,
-            'B' => 1,
-            'C' => 2,
-            'D' => 3,
-            'E' => 4,
-            'F' => 5,
-            'G' => 6,
-            'H' => 7,
-            _ => 99,
-        };
-
-        let row: usize = 7 - (chars[1].to_digit(10).expect("to row column place") as usize - 1);
+    /// Takes back the last move. The engine has no undo, so the game is
+    /// replayed from the start without it. The reply of the opening book or the
+    /// computer opponent is taken back as well so that it is the player's turn again.
+    /// A finished game carries on from there, though it stays in the archive.
+    fn undo(&mut self) {
+        if self.fixed_position.is_some() || self.move_history.is_empty() {
+            return;
+        }
+        let mut moves = self.move_history.clone();
+        moves.pop();
+        while !moves.is_empty()
+            && (self.ai_moves_at(moves.len())
+                || self
+                    .trainer
+                    .as_ref()
+                    .is_some_and(|trainer| trainer.book_to_move(moves.len())))
+        {
+            moves.pop();
+        }
 
-        (column, row)
+        self.game = Game::new();
+        for mv in &moves {
+            mv.play(&mut self.game);
+        }
+        if let Some((white_time, black_time)) = rewind_clocks(&mut self.clock_history, moves.len())
+        {
+            self.white_time = white_time;
+            self.black_time = black_time;
+        }
+        self.move_history = moves;
+        self.promoted = promoted_squares(&self.move_history);
+        self.captured = capture_counts(&self.move_history);
+        self.move_log = pgn::to_san(&self.move_history);
+        self.count_positions();
+        self.game_over = None;
+        self.game_length = None;
+        self.highlight_poses = Vec::new();
+        self.highlight_piece = None;
+        self.pending_move = None;
+        self.quick_select = None;
+        self.hover_threats = None;
+        self.capture_flash = None;
+        self.slide = None;
+        self.review = None;
+        self.ai_move_due = None;
+        self.premove_from = None;
+        self.premove = None;
+        self.load_board();
+        self.publish_position();
+        // a book move opening the line is played again straight away
+        self.play_book_moves();
     }
 
-    fn to_tuple_moves(&self, _moves: Vec<String>) -> Vec<(usize, usize)> {
-        let mut tuple_moves: Vec<(usize, usize)> = Vec::new();
-
-        for i in 0.._moves.len() {
-            tuple_moves.push(self.to_row_column(&_moves[i]));
+    /// Number of moves played in the position on the board, which is behind the
+    /// live game while reviewing.
+    fn shown_ply(&self) -> usize {
+        match &self.review {
+            Some((ply, _)) => *ply,
+            None => self.move_history.len(),
         }
-
-        tuple_moves
     }
 
-    fn add_color(&self, _color1: Color, _color2: Color) -> Color {
-        let r: f32 = _color1.r + _color2.r;
-        let g: f32 = _color1.g + _color2.g;
-        let b: f32 = _color1.b + _color2.b;
-
-        graphics::Color::new(r, g, b, 1.0)
+    /// Shows the position after the first `ply` moves, going back to the live
+    /// game once `ply` reaches the end. Like undo, the engine can't go back, so
+    /// the moves are replayed on a game of their own.
+    fn review_at(&mut self, ply: usize) {
+        if self.fixed_position.is_some() || self.pending_promotion.is_some() {
+            return;
+        }
+        self.review = if ply < self.move_history.len() {
+            let mut game = Game::new();
+            for mv in &self.move_history[..ply] {
+                mv.play(&mut game);
+            }
+            Some((ply, read_board(&game)))
+        } else {
+            None
+        };
+        self.highlight_poses = Vec::new();
+        self.highlight_piece = None;
+        self.pending_move = None;
+        self.quick_select = None;
+        self.hover_threats = None;
+        self.capture_flash = None;
+        self.slide = None;
+        self.dragging = None;
     }
 
-    #[rustfmt::skip] // Skips formatting on this function (not recommended)
-                     /// Loads chess piese images into hashmap, for ease of use.
-    fn load_sprites(ctx: &mut Context) -> HashMap<(bool, u8), graphics::Image> {
-        [
-            ((false, KING), "/black_king.png".to_string()),
-            ((false, QUEEN), "/black_queen.png".to_string()),
-            ((false, ROOK), "/black_rook.png".to_string()),
-            ((false, PAWN), "/black_pawn.png".to_string()),
-            ((false, BISHOP), "/black_bishop.png".to_string()),
-            ((false, KNIGHT), "/black_knight.png".to_string()),
-            ((true, KING), "/white_king.png".to_string()),
-            ((true, QUEEN), "/white_queen.png".to_string()),
-            ((true, ROOK), "/white_rook.png".to_string()),
-            ((true, PAWN), "/white_pawn.png".to_string()),
-            ((true, BISHOP), "/white_bishop.png".to_string()),
-            ((true, KNIGHT), "/white_knight.png".to_string())
-        ]
+    /// Adds an arrow between two squares, or a marker when both are the same
+    /// square, taking it away again if it is already there.
+    fn toggle_annotation(&mut self, from: (usize, usize), to: (usize, usize)) {
+        if from == to {
+            if !self.marked_squares.remove(&from) {
+                self.marked_squares.insert(from);
+            }
+        } else if let Some(index) = self
+            .annotations
             .iter()
-            .map(|(piece, path)| {
-                (*piece, graphics::Image::new(ctx, path).unwrap())
-            })
-            .collect::<HashMap<(bool, u8), graphics::Image>>()
-    }
-}
-
-// This is where we implement the functions that ggez requires to function
-impl event::EventHandler<GameError> for AppState {
-    /// For updating game logic, which front-end doesn't handle.
-    /// It won't be necessary to touch this unless you are implementing something that's not triggered by the user, like a clock
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        if self.game.get_game_state() == GameState::GameOver {
-            self.game = Game::new();
-            self.board = [[None; 8]; 8];
-            self.highlight_poses = Vec::new();
-            self.highlight_piece = None;
+            .position(|&arrow| arrow == (from, to))
+        {
+            self.annotations.remove(index);
+        } else {
+            self.annotations.push((from, to));
         }
-        Ok(())
     }
 
-    /// Draw interface, i.e. draw game board
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        self.load_board();
-
-        // clear interface with gray background colour
-        graphics::clear(ctx, [0.5, 0.5, 0.5, 1.0].into());
+    fn clear_annotations(&mut self) {
+        self.annotation_start = None;
+        self.annotations = Vec::new();
+        self.marked_squares = HashSet::new();
+    }
 
-        // create text representation
-        let state_text = graphics::Text::new(
-            graphics::TextFragment::from(format!("Game is {:?}.", self.game.get_game_state()))
-                .scale(graphics::PxScale { x: 30.0, y: 30.0 }),
-        );
+    /// Tallies the positions reached this game, for spotting threefold repetition.
+    fn count_positions(&mut self) {
+        let keys = fen::position_keys(&self.move_history);
+        self.repetitions = HashMap::new();
+        for key in &keys {
+            *self.repetitions.entry(key.clone()).or_insert(0) += 1;
+        }
+        self.position_key = keys.last().cloned().unwrap_or_default();
+    }
 
-        // get size of text
-        let text_dimensions = state_text.dimensions(ctx);
-        // create background rectangle with white coulouring
-        let background_box = graphics::Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            graphics::Rect::new(
-                (SCREEN_SIZE.0 - text_dimensions.w as f32) / 2f32 as f32 - 8.0,
-                (SCREEN_SIZE.0 - text_dimensions.h as f32) / 2f32 as f32,
-                text_dimensions.w as f32 + 16.0,
-                text_dimensions.h as f32,
-            ),
-            [1.0, 1.0, 1.0, 1.0].into(),
-        )?;
+    /// Whether the position on the board has now come up three times, which
+    /// lets either player claim a draw.
+    fn threefold_repetition(&self) -> bool {
+        self.repetitions
+            .get(&self.position_key)
+            .is_some_and(|&count| count >= 3)
+    }
 
-        // draw background
-        graphics::draw(ctx, &background_box, graphics::DrawParam::default())
-            .expect("Failed to draw background.");
-
-        // draw grid
-        for row in 0..8 {
-            for col in 0..8 {
-                // draw tile
-                let mut color = match col % 2 {
-                    0 => {
-                        if row % 2 == 0 {
-                            WHITE
-                        } else {
-                            BLACK
-                        }
-                    }
-                    _ => {
-                        if row % 2 == 0 {
-                            BLACK
-                        } else {
-                            WHITE
-                        }
-                    }
-                };
-                if self.highlight_poses.contains(&(col as usize, row as usize)) {
-                    color = self.add_color(color, HIGHLIGHT);
-                }
+    /// Whether the computer opponent plays the move at this ply.
+    fn ai_moves_at(&self, ply: usize) -> bool {
+        let white_to_move = ply % 2 == 0;
+        self.ai_enabled && white_to_move != self.ai_plays_black
+    }
 
-                let rectangle = graphics::Mesh::new_rectangle(
-                    ctx,
-                    graphics::DrawMode::fill(),
-                    graphics::Rect::new_i32(
-                        col * GRID_CELL_SIZE.0 as i32,
-                        row * GRID_CELL_SIZE.1 as i32,
-                        GRID_CELL_SIZE.0 as i32,
-                        GRID_CELL_SIZE.1 as i32,
-                    ),
-                    color,
-                )
-                .expect("Failed to create tile.");
+    /// Whether the computer opponent is due to move in the current game.
+    fn ai_to_move(&self) -> bool {
+        self.ai_moves_at(self.move_history.len())
+            && self.game_over.is_none()
+            && self.fixed_position.is_none()
+    }
 
-                graphics::draw(ctx, &rectangle, graphics::DrawParam::default())
-                    .expect("Failed to draw tiles.");
+    /// Switches the computer opponent from off to playing black, to playing
+    /// white and back off.
+    fn cycle_ai(&mut self) {
+        let message = match (self.ai_enabled, self.ai_plays_black) {
+            (false, _) => {
+                self.ai_enabled = true;
+                self.ai_plays_black = true;
+                "Computer plays black"
+            }
+            (true, true) => {
+                self.ai_plays_black = false;
+                "Computer plays white"
+            }
+            (true, false) => {
+                self.ai_enabled = false;
+                "Computer opponent off"
+            }
+        };
+        self.ai_move_due = None;
+        self.premove_from = None;
+        self.premove = None;
+        self.highlight_piece = None;
+        self.highlight_poses = Vec::new();
+        self.pending_move = None;
+        self.show_toast(message.to_string());
+    }
 
-                // draw piece
-                if let Some(piece) = self.board[row as usize][col as usize] {
-                    graphics::draw(
-                        ctx,
-                        self.sprites.get(&(piece.is_white, piece.role)).unwrap(),
-                        graphics::DrawParam::default()
-                            .scale([2.0, 2.0]) // Tile size is 90 pixels, while image sizes are 45 pixels.
-                            .dest([
-                                col as f32 * GRID_CELL_SIZE.0 as f32,
-                                row as f32 * GRID_CELL_SIZE.1 as f32,
-                            ]),
-                    )
-                    .expect("Failed to draw piece.");
+    /// Plays a random legal move for the side to move, promoting to a queen.
+    fn play_ai_move(&mut self) {
+        let white_to_move = self.game.is_white_turn();
+        let mut candidates = Vec::new();
+        for (from, piece) in pieces(&self.board) {
+            if piece.is_white != white_to_move {
+                continue;
+            }
+            if let Some(moves) = self.game.get_possible_moves(&square_name(from)) {
+                for to in coords::parse_squares(&moves) {
+                    candidates.push((piece, to));
                 }
             }
         }
 
-        // draw text with dark gray colouring and center position
-        graphics::draw(
-            ctx,
-            &state_text,
-            graphics::DrawParam::default()
-                .color([0.0, 0.0, 0.0, 1.0].into())
-                .dest(ggez::mint::Point2 {
-                    x: (SCREEN_SIZE.0 - text_dimensions.w as f32) / 2f32 as f32,
-                    y: (SCREEN_SIZE.0 - text_dimensions.h as f32) / 2f32 as f32,
-                }),
-        )
-        .expect("Failed to draw text.");
-
-        // render updated graphics
-        graphics::present(ctx).expect("Failed to update graphics.");
-
-        Ok(())
+        if let Some((piece, to)) = candidates.choose(&mut rand::thread_rng()).copied() {
+            let last_row = GRID_SIZE as usize - 1;
+            let promotion =
+                Some(QUEEN).filter(|_| piece.role == PAWN && (to.0 == 0 || to.0 == last_row));
+            self.highlight_piece = Some(piece);
+            self.play_selected_move(to, promotion);
+        }
     }
 
-    /// Update game on mouse click
-    fn mouse_button_up_event(
+    /// Handles a click of `button` at (x, y) in screen coordinates, from the
+    /// mouse or the keyboard cursor. `dragged_from` is the square a piece was
+    /// dragged from, when the click ends a drag.
+    fn click(
         &mut self,
-        ctx: &mut Context,
         button: event::MouseButton,
         x: f32,
         y: f32,
+        dragged_from: Option<(usize, usize)>,
     ) {
-        if button == event::MouseButton::Left {
+        // the board is only on display while presenting to the opponent or
+        // after the game ended, and waits while a resignation is being confirmed
+        if self.presenting || self.pending_resign.is_some() || self.game_over.is_some() {
+            return;
+        }
+
+        if self.review.is_some() {
+            self.show_toast(
+                "Viewing an earlier move, press Down to return to the game".to_string(),
+            );
+            return;
+        }
+
+        // while the computer opponent is thinking, clicks set up a premove
+        if self.ai_to_move() {
+            if button == self.secondary_button() {
+                self.premove_from = None;
+                self.premove = None;
+            } else if let Some(square) = self.square_at(x, y) {
+                self.premove_click(square);
+            }
+            return;
+        }
+
+        if self.fixed_position.is_some() {
+            self.show_toast("Loaded FEN positions are view only".to_string());
+            return;
+        }
+
+        if self.pending_promotion.is_some() {
+            if button == self.primary_button() {
+                self.choose_promotion(x, y);
+            }
+            return;
+        }
+
+        if button == self.secondary_button() {
+            self.clear_selection();
+            return;
+        }
+
+        if button == self.primary_button() {
             // println!("xy: {}, {}", x, y);
             // println!("xy: {}, {}", x / 90.0, y / 90.0);
 
-            let board_row: usize = (x / 90.0) as usize; // left is 0, right is 7
-            let board_column: usize = (y / 90.0) as usize; // Top is 0 bottom is 7
+            let tile_row: usize = (x / self.cell_size.0 as f32) as usize; // left is 0, right is 7
+            let tile_column: usize = (y / self.cell_size.1 as f32) as usize; // Top is 0 bottom is 7
+            if x < 0.0 || y < 0.0 || tile_row >= self.board_size || tile_column >= self.board_size {
+                return;
+            }
+            // clicks land on the screen tile; turn them back into the board square
+            let (board_column, board_row) = self.flip_square((tile_column, tile_row));
+            // only the engine's 8x8 corner of an experimental board can be played on
+            if !coords::on_board((board_column, board_row)) {
+                return;
+            }
             // println!("pressed: rowboard{}, {}", board_row, board_column);
 
-            let tmp = self.to_file_rank(board_row, board_column);
+            // a piece dropped anywhere but back on its tile or on a legal
+            // destination snaps back without moving
+            if dragged_from.is_some_and(|origin| {
+                origin != (board_column, board_row)
+                    && !self.highlight_poses.contains(&(board_column, board_row))
+            }) {
+                self.error_flash = Some(((board_column, board_row), Instant::now()));
+                return;
+            }
+
+            let tmp = square_name((board_column, board_row));
             let tmp2 = !self.board[board_column][board_row].is_none();
             println!("Filerank: {}, there is a piece: {}", tmp, tmp2);
 
-            if self.highlight_poses.contains(&(board_row, board_column)) {
+            if self.debug_grid {
+                let file_rank = square_name((board_column, board_row));
                 println!(
-                    "from: {}, to: {}",
-                    self.highlight_piece.unwrap().position.0 as usize,
-                    self.highlight_piece.unwrap().position.1 as usize
-                );
-                self.game.make_move(
-                    &self.to_file_rank(
-                        self.highlight_piece.unwrap().position.0 as usize,
-                        self.highlight_piece.unwrap().position.1 as usize,
-                    ),
-                    &self.to_file_rank(board_column, board_row),
+                    "debug: (row {}, col {}) -> {} -> {:?}",
+                    board_column,
+                    board_row,
+                    file_rank,
+                    parse_square(&file_rank)
                 );
-                self.highlight_piece = None;
-                self.highlight_poses = Vec::new();
-            } else if !self.board[board_column][board_row].is_none() {
-                // println!("first thing");
+            }
 
-                self.highlight_poses = Vec::new();
+            // with confirmation on, the first click on a destination only previews
+            // the move; any other click cancels the preview
+            let confirmed = self.pending_move.take() == Some((board_column, board_row));
+            if self.preferences.confirm_moves
+                && !confirmed
+                && self.highlight_poses.contains(&(board_column, board_row))
+            {
+                self.pending_move = Some((board_column, board_row));
+                return;
+            }
 
-                let piece = self.board[board_column][board_row].unwrap();
+            // with a piece selected, a click on anything but a legal destination or
+            // one of the player's own pieces, which picks that piece instead, is a
+            // refused move and flashes red; letting go of the piece is left to the
+            // secondary button
+            if let Some(selected) = self.highlight_piece {
+                let own_piece = self.board[board_column][board_row]
+                    .is_some_and(|piece| piece.is_white == selected.is_white);
+                if !own_piece && !self.highlight_poses.contains(&(board_column, board_row)) {
+                    self.error_flash = Some(((board_column, board_row), Instant::now()));
+                }
+            }
+
+            if self.highlight_poses.contains(&(board_column, board_row)) {
+                let moving_piece = self.highlight_piece.unwrap();
+                let last_row = GRID_SIZE as usize - 1;
+                let promotes =
+                    moving_piece.role == PAWN && (board_column == 0 || board_column == last_row);
+                if promotes && !self.preferences.auto_queen {
+                    // the move waits until a piece is picked in the promotion dialog
+                    self.pending_promotion = Some((board_column, board_row));
+                    return;
+                }
+                let promotion = Some(QUEEN).filter(|_| promotes);
+                self.play_selected_move((board_column, board_row), promotion);
+                // a dropped piece is already where it was put down
+                if dragged_from.is_some() {
+                    self.slide = None;
+                }
+            } else if !self.board[board_column][board_row].is_none() {
+                // println!("first thing");
+
+                self.highlight_poses = Vec::new();
+
+                let piece = self.board[board_column][board_row].unwrap();
                 // println!("role: {}, is white: {}", piece.role, piece.is_white);
 
-                if piece.is_white == self.game.is_white_turn() {
-                    let file_rank = self.to_file_rank(board_column, board_row);
+                if piece.is_white == self.game.is_white_turn() {
+                    let file_rank = square_name((board_column, board_row));
+
+                    // println!("Filerank: {}", file_rank);
+                    let moves = self.game.get_possible_moves(&file_rank);
+
+                    if !moves.is_none() {
+                        self.highlight_poses = coords::parse_squares(&moves.unwrap());
+                        self.highlight_piece = self.board[board_column][board_row];
+                        // TODO: convert it to positions I can use
+                    }
+                }
+
+                for item in &self.highlight_poses {
+                    println!("can move to {}, {}", item.0, item.1);
+                }
+            }
+            /* check click position and update board accordingly */
+        }
+    }
+
+    /// Lets go of the selected piece, along with any previewed move or premove.
+    fn clear_selection(&mut self) {
+        self.highlight_piece = None;
+        self.highlight_poses = Vec::new();
+        self.pending_move = None;
+        self.quick_select = None;
+        self.premove = None;
+    }
+
+    /// Shows the keyboard cursor on the king of the side to move, or once it
+    /// is showing, clicks the square under it.
+    fn press_cursor(&mut self) {
+        match self.keyboard_cursor {
+            Some(square) => {
+                let [x, y] = self.tile_center(square);
+                self.click(self.primary_button(), x, y, None);
+            }
+            None => {
+                let king = attacks::king_square(&self.board, self.game.is_white_turn());
+                self.keyboard_cursor = Some(king.unwrap_or((0, 0)));
+                self.show_toast(
+                    "Arrow keys move the cursor, Enter picks a square, Escape hides it".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Moves the keyboard cursor by `rows` and `cols` tiles as seen on screen,
+    /// stopping at the edge of the board.
+    fn move_cursor(&mut self, rows: i32, cols: i32) {
+        let Some(cursor) = self.keyboard_cursor else {
+            return;
+        };
+        let tile = self.flip_square(cursor);
+        let last = self.board_size as i32 - 1;
+        let moved = (
+            (tile.0 as i32 + rows).clamp(0, last) as usize,
+            (tile.1 as i32 + cols).clamp(0, last) as usize,
+        );
+        self.keyboard_cursor = Some(self.flip_square(moved));
+    }
+
+    /// Queues the player's next move while the computer opponent is thinking:
+    /// a click on one of the player's pieces picks it, the next click anywhere
+    /// else is its destination. Legality is only checked when it is played.
+    fn premove_click(&mut self, square: (usize, usize)) {
+        let players_piece = self.board[square.0][square.1]
+            .is_some_and(|piece| piece.is_white != self.game.is_white_turn());
+        if players_piece {
+            self.premove_from = Some(square);
+            self.premove = None;
+        } else if let Some(from) = self.premove_from.take() {
+            let last_row = GRID_SIZE as usize - 1;
+            let promotes = self.board[from.0][from.1].is_some_and(|piece| piece.role == PAWN)
+                && (square.0 == 0 || square.0 == last_row);
+            self.premove = Some(Move {
+                from: square_name(from),
+                to: square_name(square),
+                promotion: Some(QUEEN).filter(|_| promotes),
+            });
+        }
+    }
+
+    /// Plays the queued premove if it is legal now, or else drops it.
+    fn play_premove(&mut self, premove: Move) {
+        let (Some(from), Some(to)) = (parse_square(&premove.from), parse_square(&premove.to))
+        else {
+            return;
+        };
+        self.load_board();
+        let legal = self
+            .game
+            .get_possible_moves(&premove.from)
+            .is_some_and(|moves| coords::parse_squares(&moves).contains(&to));
+        if legal {
+            self.highlight_piece = self.board[from.0][from.1];
+            self.play_selected_move(to, premove.promotion);
+        }
+    }
+
+    /// Plays the trainer's side of the opening line until it is the player's turn.
+    fn play_book_moves(&mut self) {
+        let trainer = match &self.trainer {
+            Some(trainer) => trainer,
+            None => return,
+        };
+        let mut played = false;
+        while trainer.book_to_move(self.move_history.len()) {
+            let book_move = trainer.book_move(self.move_history.len()).unwrap().clone();
+            let squares = (parse_square(&book_move.from), parse_square(&book_move.to));
+            if let (Some(from), Some(to)) = squares {
+                let board = read_board(&self.game);
+                if let Some(piece) = taken_piece(&board, from, to) {
+                    count_capture(&mut self.captured, piece);
+                }
+                track_promoted(&mut self.promoted, &board, from, to);
+            }
+            book_move.play(&mut self.game);
+            self.clock_history.push((self.white_time, self.black_time));
+            self.move_history.push(book_move);
+            played = true;
+        }
+        if played {
+            self.move_log = pgn::to_san(&self.move_history);
+            self.count_positions();
+            self.publish_position();
+        }
+    }
+
+    /// The state of the game in words: who is to move or in check, or how it ended.
+    fn game_state_text(&self) -> String {
+        if let Some(reason) = &self.game_over {
+            return reason.clone();
+        }
+        let (side, other) = if self.game.is_white_turn() {
+            ("White", "Black")
+        } else {
+            ("Black", "White")
+        };
+        match self.game.get_game_state() {
+            GameState::InProgress => format!("{} to move", side),
+            GameState::Check => format!("{} is in check", side),
+            // the engine doesn't tell checkmate from stalemate
+            GameState::GameOver if attacks::in_check(&self.board, self.game.is_white_turn()) => {
+                format!("Checkmate — {} wins", other)
+            }
+            GameState::GameOver => "Stalemate — the game is drawn".to_string(),
+        }
+    }
+
+    /// Time since the game began, stopped once it is over.
+    fn elapsed(&self) -> Duration {
+        self.game_length
+            .unwrap_or_else(|| self.game_started.elapsed())
+    }
+
+    /// Lines of the status text shown over the board.
+    fn status_text(&self) -> String {
+        let mut lines = vec![self.game_state_text()];
+        if let Some(trainer) = &self.trainer {
+            let ply = self.move_history.len().min(trainer.len());
+            lines.push(format!("Opening line: {} of {} moves", ply, trainer.len()));
+        }
+        let elapsed = self.elapsed().as_secs();
+        lines.push(format!(
+            "Move {}, {:02}:{:02}",
+            self.move_log.len() / 2 + 1,
+            elapsed / 60,
+            elapsed % 60
+        ));
+        if self.preferences.show_game_phase {
+            let fullmove = self.move_history.len() / 2 + 1;
+            lines.push(format!("{:?}", GamePhase::of(&self.board, fullmove)));
+        }
+        lines.join("\n")
+    }
+
+    /// FEN of the position on the board.
+    fn current_fen(&self) -> String {
+        match &self.fixed_position {
+            Some((_, fen)) => fen.clone(),
+            None => fen::from_moves(&self.move_history),
+        }
+    }
+
+    /// Loads a FEN position or PGN game from the system clipboard.
+    fn import_from_clipboard(&mut self) {
+        match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+            Ok(text) => self.import_text(&text),
+            Err(err) => self.show_toast(format!("Could not read the clipboard: {}", err)),
+        }
+    }
+
+    /// Replaces the current game with a FEN position or PGN game, telling the
+    /// two apart by the FEN's eight slash-separated ranks. Leaves the opening
+    /// trainer, since the imported game won't follow its line.
+    fn import_text(&mut self, text: &str) {
+        self.trainer = None;
+        let looks_like_fen = text
+            .split_whitespace()
+            .next()
+            .is_some_and(|first| first.matches('/').count() == 7);
+
+        if looks_like_fen {
+            match fen::parse(text) {
+                Ok((board, white_to_move)) => {
+                    self.new_game();
+                    self.fixed_position = Some((board, text.trim().to_string()));
+                    self.publish_position();
+                    self.show_toast(format!(
+                        "Loaded position, {} to move (view only)",
+                        if white_to_move { "white" } else { "black" }
+                    ));
+                }
+                Err(err) => self.show_toast(format!("Invalid FEN: {}", err)),
+            }
+        } else {
+            match pgn::parse(text) {
+                Ok(moves) if moves.is_empty() => self.show_toast("No moves to load".to_string()),
+                Ok(moves) => {
+                    self.show_toast(format!("Loaded game of {} moves", moves.len()));
+                    self.replace_moves(moves);
+                }
+                Err(err) => self.show_toast(format!("Could not load game: {}", err)),
+            }
+        }
+    }
+
+    /// Runs what was typed on the command line: a FEN loads that position and
+    /// a move such as "e2e4" or "e7e8n" is played. The line stays open with
+    /// an error beside it when the command can't be run.
+    fn submit_command(&mut self) {
+        let command = self.command_buffer.take().unwrap_or_default();
+        let command = command.trim();
+        let result = if command.contains('/') {
+            fen::parse(command).map(|_| self.import_text(command))
+        } else if let Some((from, to)) = coords::parse_uci_move(command) {
+            self.play_typed_move(from, to, command.chars().nth(4))
+        } else {
+            Err("Type a FEN or a move such as e2e4".to_string())
+        };
+
+        self.command_error = result.err();
+        if self.command_error.is_some() {
+            self.command_buffer = Some(command.to_string());
+        }
+    }
+
+    /// Plays a move typed as the (row, column) squares it goes between, with
+    /// `promotion` naming the piece a pawn becomes (a queen if it is None).
+    fn play_typed_move(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Option<char>,
+    ) -> Result<(), String> {
+        if self.game_over.is_some() {
+            return Err("The game is over".to_string());
+        }
+        if self.fixed_position.is_some() {
+            return Err("Loaded FEN positions are view only".to_string());
+        }
+        if self.review.is_some() {
+            return Err("Press Down to return to the game first".to_string());
+        }
+        if self.ai_to_move() || self.pending_promotion.is_some() {
+            return Err("Wait for your turn".to_string());
+        }
+
+        self.load_board();
+        let piece = self.board[from.0][from.1]
+            .filter(|piece| piece.is_white == self.game.is_white_turn())
+            .ok_or_else(|| format!("No piece of yours on {}", square_name(from).to_lowercase()))?;
+        let legal = self
+            .game
+            .get_possible_moves(&square_name(from))
+            .is_some_and(|moves| coords::parse_squares(&moves).contains(&to));
+        if !legal {
+            return Err(self.rejected_move_message(piece, to));
+        }
+
+        let mv = pgn::with_promotion(&self.board, from, to, promotion);
+        self.highlight_piece = Some(piece);
+        self.play_selected_move(to, mv.promotion);
+        Ok(())
+    }
+
+    /// Starts a new game with `moves`, which must all be legal, already played.
+    fn replace_moves(&mut self, moves: Vec<Move>) {
+        self.new_game();
+        for mv in &moves {
+            mv.play(&mut self.game);
+        }
+        // the clocks have not run for moves that were played elsewhere
+        self.clock_history = vec![(self.white_time, self.black_time); moves.len()];
+        self.move_history = moves;
+        self.promoted = promoted_squares(&self.move_history);
+        self.captured = capture_counts(&self.move_history);
+        self.move_log = pgn::to_san(&self.move_history);
+        self.count_positions();
+        self.publish_position();
+    }
+
+    /// Saves the game in progress to `SAVE_PATH`.
+    fn save_game(&mut self) {
+        match save::save(SAVE_PATH, &self.move_history, self.game.is_white_turn()) {
+            Ok(()) => self.show_toast(format!("Game saved to {}", SAVE_PATH)),
+            Err(err) => self.show_toast(format!("Could not save game: {}", err)),
+        }
+    }
+
+    /// Resumes the game saved in `SAVE_PATH`, keeping the current one if the
+    /// file can't be read or its moves don't replay. Leaves the opening trainer
+    /// like any other loaded game.
+    fn load_game(&mut self) {
+        match save::load(SAVE_PATH) {
+            Ok(moves) => {
+                self.trainer = None;
+                self.show_toast(format!("Resumed game of {} moves", moves.len()));
+                self.replace_moves(moves);
+            }
+            Err(err) => self.show_toast(format!("Could not load game: {}", err)),
+        }
+    }
+
+    /// Hands the current position to the spectator endpoint, if it is running,
+    /// and to the crash reporter.
+    fn publish_position(&self) {
+        let fen = self.current_fen();
+        if let Some(spectator) = &self.spectator {
+            spectator.publish(fen.clone());
+        }
+        self.crash_reporter
+            .record_position(fen, Game::get_board(&self.game));
+    }
+
+    /// Archives the game with the given PGN result and says why it ended.
+    fn archive_game(&mut self, result: &str, reason: &str) {
+        let path = self.preferences.archive_path.clone();
+        match pgn::append_game(&path, &self.move_history, result) {
+            Ok(()) => self.show_toast(format!("{}. Game saved to {}", reason, path)),
+            Err(err) => self.show_toast(format!("{}. Could not save game: {}", reason, err)),
+        }
+    }
+
+    /// Window title naming the side to move, or the result once the game is over.
+    fn title(&self) -> String {
+        match &self.game_over {
+            Some(reason) => format!("Schack — {}", reason),
+            None if self.game.is_white_turn() => "Schack — White to move".to_string(),
+            None => "Schack — Black to move".to_string(),
+        }
+    }
+
+    /// Whether the side to move's clock is counting down. Clocks start with
+    /// the first move and don't run for loaded FEN positions.
+    fn clock_running(&self) -> bool {
+        self.game_over.is_none() && self.fixed_position.is_none() && !self.move_history.is_empty()
+    }
+
+    /// Ends the game with the given PGN result, leaving the final position and
+    /// the reason on screen until a new game is started.
+    fn end_game(&mut self, result: &str, reason: &str) {
+        self.archive_game(result, reason);
+        self.game_over = Some(reason.to_string());
+        self.game_length = Some(self.game_started.elapsed());
+        self.highlight_piece = None;
+        self.highlight_poses = Vec::new();
+        self.pending_move = None;
+        self.dragging = None;
+        self.premove_from = None;
+        self.premove = None;
+    }
+
+    /// Ends the game as drawn once fifty moves have gone by without a pawn move
+    /// or capture, or when neither side can checkmate any more. The engine
+    /// doesn't report either.
+    fn check_for_draw(&mut self) {
+        self.load_board();
+        if fen::halfmove_clock(&self.move_history) >= 100 {
+            self.play_game_over_sound(false);
+            self.end_game("1/2-1/2", "Draw by the fifty-move rule");
+        } else if insufficient_material(&self.board) {
+            self.play_game_over_sound(false);
+            self.end_game("1/2-1/2", "Draw by insufficient material");
+        }
+    }
+
+    /// Ends the game on time for the side whose clock ran out.
+    fn flag_fall(&mut self, white: bool) {
+        self.play_game_over_sound(true);
+        if white {
+            self.end_game("0-1", "White's flag fell, black wins");
+        } else {
+            self.end_game("1-0", "Black's flag fell, white wins");
+        }
+    }
+
+    /// Plays the fanfare for a game won over the board, or the quieter sound
+    /// for a drawn one.
+    fn play_game_over_sound(&mut self, decisive: bool) {
+        let sound = if decisive {
+            &mut self.checkmate_sound
+        } else {
+            &mut self.draw_sound
+        };
+        play_sound(sound, self.preferences.sound_volume());
+    }
+
+    /// Concedes the game for the side to move.
+    fn resign(&mut self) {
+        self.pending_resign = None;
+        if self.game.is_white_turn() {
+            self.end_game("0-1", "White resigns, black wins");
+        } else {
+            self.end_game("1-0", "Black resigns, white wins");
+        }
+    }
+
+    /// Mouse button that selects and moves pieces, honouring the left-handed preference.
+    fn primary_button(&self) -> event::MouseButton {
+        if self.preferences.swap_buttons {
+            event::MouseButton::Right
+        } else {
+            event::MouseButton::Left
+        }
+    }
+
+    /// The other of the two main mouse buttons, which lets go of a selected piece.
+    fn secondary_button(&self) -> event::MouseButton {
+        match self.primary_button() {
+            event::MouseButton::Left => event::MouseButton::Right,
+            _ => event::MouseButton::Left,
+        }
+    }
+
+    /// Stops background work and saves settings before the window closes.
+    ///
+    /// ggez exits the process from inside its event loop without dropping the
+    /// state, so the spectator server has to be stopped here rather than by Drop.
+    fn shut_down(&mut self) {
+        // dropping the server unblocks and joins its thread
+        self.spectator = None;
+        self.preferences.save();
+    }
+
+    /// Selects the next piece of the given role, for the side to move, that has a
+    /// legal move, starting over from the first on a new role.
+    fn quick_select(&mut self, role: u8) {
+        if self.presenting || self.fixed_position.is_some() || self.review.is_some() {
+            return;
+        }
+
+        let white = self.game.is_white_turn();
+        let movable: Vec<((usize, usize), Vec<String>)> = pieces(&self.board)
+            .filter(|(_, piece)| piece.role == role && piece.is_white == white)
+            .filter_map(|(square, _)| {
+                self.game
+                    .get_possible_moves(&square_name(square))
+                    .filter(|moves| !moves.is_empty())
+                    .map(|moves| (square, moves))
+            })
+            .collect();
+        if movable.is_empty() {
+            self.show_toast(format!("No {} can move", role_name(role)));
+            return;
+        }
+
+        let index = match self.quick_select {
+            Some((last_role, last_index)) if last_role == role => (last_index + 1) % movable.len(),
+            _ => 0,
+        };
+        self.quick_select = Some((role, index));
+
+        let ((row, col), moves) = movable[index].clone();
+        self.highlight_poses = coords::parse_squares(&moves);
+        self.highlight_piece = self.board[row][col];
+        self.pending_move = None;
+    }
+
+    /// Plays the selected piece to the (row, column) square `to`, promoting to
+    /// `promotion` if it is a pawn reaching the last rank, and clears the selection.
+    fn play_selected_move(&mut self, to: (usize, usize), promotion: Option<u8>) {
+        let moving_piece = match self.highlight_piece {
+            Some(piece) => piece,
+            None => return,
+        };
+        let from_square = (
+            moving_piece.position.0 as usize,
+            moving_piece.position.1 as usize,
+        );
+        let mv = Move {
+            from: square_name(from_square),
+            to: square_name(to),
+            promotion,
+        };
+        self.highlight_piece = None;
+        self.highlight_poses = Vec::new();
+
+        if let Some(trainer) = &self.trainer {
+            let ply = self.move_history.len();
+            if let Verdict::Rejected(hint) = trainer.check(ply, &mv, &self.board) {
+                self.show_toast(hint);
+                return;
+            }
+        }
+        let was_white_turn = self.game.is_white_turn();
+        let taken = taken_piece(&self.board, from_square, to);
+        let is_capture = taken.is_some();
+
+        mv.play(&mut self.game);
+        // the engine hands the turn over only when the move was accepted
+        if self.game.is_white_turn() != was_white_turn {
+            self.clock_history.push((self.white_time, self.black_time));
+            self.move_history.push(mv);
+            if let Some(piece) = taken {
+                count_capture(&mut self.captured, piece);
+            }
+            track_promoted(&mut self.promoted, &self.board, from_square, to);
+            self.move_log = pgn::to_san(&self.move_history);
+            self.count_positions();
+            self.publish_position();
+            let mut slides = vec![(from_square, to)];
+            // castling moves the rook over the king as well
+            let is_castle = moving_piece.role == KING && from_square.1.abs_diff(to.1) == 2;
+            if is_castle {
+                let (rook_from, rook_to) = if to.1 > from_square.1 { (7, 5) } else { (0, 3) };
+                slides.push(((to.0, rook_from), (to.0, rook_to)));
+            }
+            self.slide = Some((slides, Instant::now()));
+            if is_capture {
+                self.capture_flash = Some((to, Instant::now()));
+            }
+            // a check is worth hearing about over whatever else the move did
+            let gives_check = attacks::in_check(&read_board(&self.game), self.game.is_white_turn());
+            let sound = if gives_check {
+                &mut self.check_sound
+            } else if is_capture {
+                &mut self.capture_sound
+            } else if is_castle {
+                &mut self.castle_sound
+            } else {
+                &mut self.move_sound
+            };
+            play_sound(sound, self.preferences.sound_volume());
+            if let Some(trainer) = &self.trainer {
+                if self.move_history.len() == trainer.len() {
+                    self.show_toast("Opening line complete".to_string());
+                }
+            }
+            self.check_for_draw();
+            if self.game_over.is_none() {
+                self.play_book_moves();
+            }
+        } else {
+            let message = self.rejected_move_message(moving_piece, to);
+            self.show_toast(message);
+        }
+    }
+
+    /// Board (row, column) square under the screen position (x, y), if it is
+    /// one the engine plays on.
+    fn square_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let tile = (
+            (y / self.cell_size.1 as f32) as usize,
+            (x / self.cell_size.0 as f32) as usize,
+        );
+        if x < 0.0 || y < 0.0 || tile.0 >= self.board_size || tile.1 >= self.board_size {
+            return None;
+        }
+        Some(self.flip_square(tile)).filter(|square| coords::on_board(*square))
+    }
+
+    /// Screen bounds of the promotion dialog's choice at `index` in `PROMOTION_CHOICES`.
+    fn promotion_choice_bounds(&self, index: usize) -> graphics::Rect {
+        let board = board_extent(self.board_size, self.cell_size);
+        let width = self.cell_size.0 as f32 * PROMOTION_CHOICES.len() as f32;
+        graphics::Rect::new(
+            (board.0 - width) / 2.0 + index as f32 * self.cell_size.0 as f32,
+            (board.1 - self.cell_size.1 as f32) / 2.0,
+            self.cell_size.0 as f32,
+            self.cell_size.1 as f32,
+        )
+    }
+
+    /// Answers the promotion dialog with a click: a choice finishes the move,
+    /// anywhere else calls it off.
+    fn choose_promotion(&mut self, x: f32, y: f32) {
+        let to = match self.pending_promotion.take() {
+            Some(to) => to,
+            None => return,
+        };
+        let choice = (0..PROMOTION_CHOICES.len())
+            .find(|index| self.promotion_choice_bounds(*index).contains([x, y]))
+            .map(|index| PROMOTION_CHOICES[index]);
+
+        match choice {
+            Some(role) => self.play_selected_move(to, Some(role)),
+            None => self.cancel_promotion(),
+        }
+    }
+
+    /// Drops the promoting move and the selection along with it.
+    fn cancel_promotion(&mut self) {
+        self.pending_promotion = None;
+        self.highlight_piece = None;
+        self.highlight_poses = Vec::new();
+    }
+
+    /// Dims the board and offers the pieces a pawn can promote to.
+    fn draw_promotion_dialog(&self, ctx: &mut Context) -> GameResult {
+        let is_white = match (self.pending_promotion, self.highlight_piece) {
+            (Some(_), Some(piece)) => piece.is_white,
+            _ => return Ok(()),
+        };
+        let screen = screen_size(self.board_size, self.cell_size);
+
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, screen.0, screen.1),
+            [0.0, 0.0, 0.0, 0.5].into(),
+        )?;
+        graphics::draw(ctx, &shade, graphics::DrawParam::default())?;
+
+        for (index, role) in PROMOTION_CHOICES.iter().enumerate() {
+            let bounds = self.promotion_choice_bounds(index);
+            let background = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                bounds,
+                [1.0, 1.0, 1.0, 1.0].into(),
+            )?;
+            graphics::draw(ctx, &background, graphics::DrawParam::default())?;
+
+            let piece = Piece::new(*role, (0, 0), is_white);
+            self.draw_piece_at(ctx, piece, [bounds.x, bounds.y], bounds.w, 1.0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws the previewed sprite theme's six pieces in a strip over the board,
+    /// with its name and the keys for moving on, applying or cancelling.
+    fn draw_sprite_preview(&self, ctx: &mut Context) -> GameResult {
+        let (theme, sprites) = match &self.sprite_preview {
+            Some(preview) => preview,
+            None => return Ok(()),
+        };
+        let board = board_extent(self.board_size, self.cell_size);
+        let size = self.cell_size.0 as f32 * 0.75;
+        let roles = [KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN];
+        let strip_width = size * roles.len() as f32;
+
+        let title = graphics::Text::new(
+            graphics::TextFragment::from(format!("Pieces: {}", theme))
+                .scale(graphics::PxScale { x: 24.0, y: 24.0 }),
+        );
+        let hint = graphics::Text::new(
+            graphics::TextFragment::from("Shift+T next set, Enter apply, Escape cancel")
+                .scale(graphics::PxScale { x: 16.0, y: 16.0 }),
+        );
+        let (title_size, hint_size) = (title.dimensions(ctx), hint.dimensions(ctx));
+        let width = strip_width.max(hint_size.w) + 32.0;
+        let height = title_size.h + size * 2.0 + hint_size.h + 48.0;
+        let corner = [(board.0 - width) / 2.0, (board.1 - height) / 2.0];
+
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(corner[0], corner[1], width, height),
+            [0.85, 0.85, 0.85, 0.95].into(),
+        )?;
+        graphics::draw(ctx, &background, graphics::DrawParam::default())?;
+        graphics::draw(
+            ctx,
+            &title,
+            graphics::DrawParam::default()
+                .color([0.0, 0.0, 0.0, 1.0].into())
+                .dest([corner[0] + (width - title_size.w) / 2.0, corner[1] + 16.0]),
+        )?;
+
+        // a row of white pieces above a row of black ones
+        let left = corner[0] + (width - strip_width) / 2.0;
+        let top = corner[1] + title_size.h + 24.0;
+        for (row, is_white) in [true, false].into_iter().enumerate() {
+            for (index, role) in roles.into_iter().enumerate() {
+                let piece = Piece::new(role, (0, 0), is_white);
+                let piece_corner = [left + index as f32 * size, top + row as f32 * size];
+                self.draw_themed_piece_at(ctx, sprites, piece, piece_corner, size, 1.0)?;
+            }
+        }
+
+        graphics::draw(
+            ctx,
+            &hint,
+            graphics::DrawParam::default()
+                .color([0.0, 0.0, 0.0, 1.0].into())
+                .dest([
+                    corner[0] + (width - hint_size.w) / 2.0,
+                    top + size * 2.0 + 8.0,
+                ]),
+        )
+    }
+
+    /// Whether moving the selected piece to the (row, column) square `to` takes
+    /// en passant, that is a pawn moving diagonally onto an empty square.
+    fn is_en_passant(&self, to: (usize, usize)) -> bool {
+        self.highlight_piece
+            .is_some_and(|piece| piece.role == PAWN && piece.position.1 as usize != to.1)
+            && self.board[to.0][to.1].is_none()
+    }
+
+    /// Squares of the rooks the selected piece can castle with, found from
+    /// the king's two-file moves among its legal moves.
+    fn castling_rooks(&self) -> Vec<(usize, usize)> {
+        let king = match self.highlight_piece.filter(|piece| piece.role == KING) {
+            Some(king) => (king.position.0 as usize, king.position.1 as usize),
+            None => return Vec::new(),
+        };
+        self.highlight_poses
+            .iter()
+            .filter(|to| to.0 == king.0 && to.1.abs_diff(king.1) == 2)
+            .map(|to| (king.0, if to.1 > king.1 { 7 } else { 0 }))
+            .collect()
+    }
+
+    /// Explains why the engine refused to move `piece` to the (row, column)
+    /// square `to`. The engine gives no reason, so castling is recognised by
+    /// the king moving two files and checked square by square.
+    fn rejected_move_message(&self, piece: Piece, to: (usize, usize)) -> String {
+        let from = (piece.position.0 as usize, piece.position.1 as usize);
+        if piece.role != KING || from.0 != to.0 || from.1.abs_diff(to.1) != 2 {
+            return "That move isn't legal".to_string();
+        }
+
+        let passed = (from.0, (from.1 + to.1) / 2);
+        let attacked = |square| attacks::is_attacked(&self.board, square, !piece.is_white);
+        if attacked(from) {
+            "Cannot castle out of check".to_string()
+        } else if attacked(passed) {
+            "Cannot castle through check".to_string()
+        } else if attacked(to) {
+            "Cannot castle into check".to_string()
+        } else {
+            "Cannot castle here".to_string()
+        }
+    }
+
+    /// Writes the current position as FEN to `FEN_EXPORT_PATH`.
+    fn export_fen(&mut self) {
+        let fen = self.current_fen();
+        match std::fs::write(FEN_EXPORT_PATH, format!("{}\n", fen)) {
+            Ok(()) => self.show_toast(format!("Position saved to {}", FEN_EXPORT_PATH)),
+            Err(err) => self.show_toast(format!("Could not write {}: {}", FEN_EXPORT_PATH, err)),
+        }
+    }
+
+    /// Saves the frame just drawn as a PNG named after the current time in the
+    /// working directory.
+    fn save_screenshot(&mut self, ctx: &mut Context) {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let path = format!("screenshot-{}.png", seconds);
+        let result = graphics::screenshot(ctx)
+            .and_then(|shot| Ok((shot.to_rgba8(ctx)?, shot.width(), shot.height())))
+            .map_err(|err| err.to_string())
+            .and_then(|(rgba, width, height)| {
+                image::save_buffer(
+                    &path,
+                    &rgba,
+                    width as u32,
+                    height as u32,
+                    image::ColorType::Rgba8,
+                )
+                .map_err(|err| err.to_string())
+            });
+        match result {
+            Ok(()) => self.show_toast(format!("Screenshot saved to {}", path)),
+            Err(err) => self.show_toast(format!("Could not save screenshot: {}", err)),
+        }
+    }
+
+    /// Copies the FEN of the position on the board to the system clipboard,
+    /// printing it instead if the clipboard can't be reached.
+    fn copy_fen(&mut self) {
+        let fen = self.current_fen();
+        match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(fen.clone())) {
+            Ok(()) => self.show_toast("FEN copied".to_string()),
+            Err(err) => {
+                println!("{}", fen);
+                self.show_toast(format!("Could not copy, FEN printed instead: {}", err));
+            }
+        }
+    }
+
+    fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
+
+    /// Opacity of the capture flash on the (row, column) square, if it is still fading out.
+    fn capture_flash_alpha(&self, square: (usize, usize)) -> Option<f32> {
+        let (flash_square, started) = self.capture_flash?;
+        if flash_square != square || self.preferences.animation_speed <= 0.0 {
+            return None;
+        }
+        let duration = CAPTURE_FLASH_DURATION.as_secs_f32() / self.preferences.animation_speed;
+        let progress = started.elapsed().as_secs_f32() / duration;
+        if progress < 1.0 {
+            Some(1.0 - progress)
+        } else {
+            None
+        }
+    }
+
+    /// Strength, from 0 to 1, of the selected piece's highlights as they fade in.
+    fn selection_alpha(&self) -> f32 {
+        match self.selection_fade {
+            Some((_, started)) if self.preferences.animation_speed > 0.0 => {
+                let duration =
+                    SELECTION_FADE_DURATION.as_secs_f32() / self.preferences.animation_speed;
+                (started.elapsed().as_secs_f32() / duration).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Opacity of the red flash on the (row, column) square, if it is still showing.
+    /// Unlike the board effects it ignores the animation speed, since it is
+    /// feedback the player needs.
+    fn error_flash_alpha(&self, square: (usize, usize)) -> Option<f32> {
+        let (flash_square, started) = self.error_flash?;
+        let progress = started.elapsed().as_secs_f32() / ERROR_FLASH_DURATION.as_secs_f32();
+        if flash_square == square && progress < 1.0 {
+            Some(0.6 * (1.0 - progress))
+        } else {
+            None
+        }
+    }
+
+    /// Whether anything on screen is still changing by itself, so that the
+    /// next frame has to be drawn even without input.
+    fn animating(&self) -> bool {
+        self.slide_progress().is_some()
+            || self
+                .capture_flash
+                .is_some_and(|(square, _)| self.capture_flash_alpha(square).is_some())
+            || self
+                .error_flash
+                .is_some_and(|(square, _)| self.error_flash_alpha(square).is_some())
+            || self.selection_alpha() < 1.0
+            || self
+                .toast
+                .as_ref()
+                .is_some_and(|(_, shown_at)| shown_at.elapsed() < TOAST_DURATION)
+    }
+
+    /// How far, from 0 to 1, the last moved piece has slid towards its square,
+    /// if it is still moving.
+    fn slide_progress(&self) -> Option<f32> {
+        let (_, started) = self.slide.as_ref()?;
+        if self.preferences.animation_speed <= 0.0 {
+            return None;
+        }
+        let duration = SLIDE_DURATION.as_secs_f32() / self.preferences.animation_speed;
+        let progress = started.elapsed().as_secs_f32() / duration;
+        if progress < 1.0 {
+            Some(progress)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the board is currently drawn from black's side. Presenting turns
+    /// the board to the opponent, whichever way round it was.
+    fn view_flipped(&self) -> bool {
+        self.flipped != self.presenting
+    }
+
+    /// Maps a (row, column) square between board and screen layout. Rotating the
+    /// board is its own inverse, so the same mapping works in both directions.
+    fn flip_square(&self, square: (usize, usize)) -> (usize, usize) {
+        if self.view_flipped() {
+            (
+                self.board_size - 1 - square.0,
+                self.board_size - 1 - square.1,
+            )
+        } else {
+            square
+        }
+    }
+
+    /// Shows the board turned around and fullscreen so the opponent can read it.
+    ///
+    /// ggez 0.6 drives a single window, so a true second window for the
+    /// opponent isn't possible; this temporary flip stands in for it.
+    fn toggle_presenting(&mut self, ctx: &mut Context) {
+        self.presenting = !self.presenting;
+        self.update_fullscreen(ctx);
+        if self.presenting {
+            self.show_toast("Showing the board to your opponent, press P to return".to_string());
+        }
+    }
+
+    /// Switches between a window and filling the screen.
+    fn toggle_fullscreen(&mut self, ctx: &mut Context) {
+        self.fullscreen = !self.fullscreen;
+        self.update_fullscreen(ctx);
+    }
+
+    /// Fills the screen while either fullscreen or presenting asks for it, and
+    /// otherwise goes back to a window of the original size. The board is
+    /// rescaled and centred by `resize_event` once the window has changed.
+    fn update_fullscreen(&mut self, ctx: &mut Context) {
+        let result = if self.fullscreen || self.presenting {
+            graphics::set_fullscreen(ctx, conf::FullscreenType::Desktop)
+        } else {
+            let screen = screen_size(self.board_size, self.cell_size);
+            graphics::set_fullscreen(ctx, conf::FullscreenType::Windowed)
+                .and_then(|()| graphics::set_drawable_size(ctx, screen.0, screen.1))
+        };
+        if let Err(err) = result {
+            eprintln!("Could not change fullscreen mode: {}", err);
+        }
+    }
+
+    /// Tile colours of the board, as set in the preferences.
+    fn theme(&self) -> Theme {
+        preferred_theme(&self.preferences)
+    }
+
+    /// The plain checkerboard of a theme as a single mesh, light on a8 and h1.
+    /// Flipping the board turns it half around, which leaves the pattern alone.
+    fn build_board_mesh(
+        ctx: &mut Context,
+        board_size: usize,
+        cell_size: (i16, i16),
+        theme: &Theme,
+    ) -> GameResult<graphics::Mesh> {
+        let mut builder = graphics::MeshBuilder::new();
+        for row in 0..board_size as i32 {
+            for col in 0..board_size as i32 {
+                let color = if (row + col) % 2 == 0 {
+                    theme.light
+                } else {
+                    theme.dark
+                };
+                builder.rectangle(
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new_i32(
+                        col * cell_size.0 as i32,
+                        row * cell_size.1 as i32,
+                        cell_size.0 as i32,
+                        cell_size.1 as i32,
+                    ),
+                    color,
+                )?;
+            }
+        }
+        builder.build(ctx)
+    }
+
+    /// Switches to the next board theme, wrapping around.
+    fn next_theme(&mut self) {
+        let index = THEMES
+            .iter()
+            .position(|theme| theme.name == self.preferences.board_theme)
+            .map_or(0, |index| (index + 1) % THEMES.len());
+        self.preferences.board_theme = THEMES[index].name.to_string();
+        self.preferences.save();
+    }
+
+    /// Sum of two colours, channel by channel, as an opaque colour. Highlights
+    /// are tints meant to be added on, so their alpha isn't used, and each
+    /// channel is clamped since light tiles plus a tint can pass full brightness.
+    fn add_color(_color1: Color, _color2: Color) -> Color {
+        let r: f32 = (_color1.r + _color2.r).clamp(0.0, 1.0);
+        let g: f32 = (_color1.g + _color2.g).clamp(0.0, 1.0);
+        let b: f32 = (_color1.b + _color2.b).clamp(0.0, 1.0);
+
+        graphics::Color::new(r, g, b, 1.0)
+    }
+
+    /// Loads the piece images of a sprite theme into a hashmap, for ease of
+    /// use. The default theme's images sit directly in the resources directory
+    /// and every other theme's in a directory named after it. Images that can't
+    /// be loaded are left out, to be drawn from the default theme or as letters.
+    fn load_sprites(ctx: &mut Context, theme: &str) -> HashMap<(bool, u8), graphics::Image> {
+        let directory = if theme == DEFAULT_SPRITE_THEME {
+            String::new()
+        } else {
+            format!("/{}", theme)
+        };
+        let mut sprites = HashMap::new();
+        for is_white in [false, true] {
+            for role in [KING, QUEEN, ROOK, PAWN, BISHOP, KNIGHT] {
+                let path = format!(
+                    "{}/{}_{}.png",
+                    directory,
+                    if is_white { "white" } else { "black" },
+                    role_name(role)
+                );
+                match graphics::Image::new(ctx, &path) {
+                    Ok(image) => {
+                        sprites.insert((is_white, role), image);
+                    }
+                    Err(err) => eprintln!("Could not load sprite {}: {}", path, err),
+                }
+            }
+        }
+        sprites
+    }
+
+    /// Loads the piece images of a sprite theme other than the default, which
+    /// is always loaded anyway.
+    fn load_theme_sprites(ctx: &mut Context, theme: &str) -> HashMap<(bool, u8), graphics::Image> {
+        if theme == DEFAULT_SPRITE_THEME {
+            HashMap::new()
+        } else {
+            AppState::load_sprites(ctx, theme)
+        }
+    }
+
+    /// Names of the sprite themes: the default one, then every directory in the
+    /// resources directory holding a white king, alphabetically.
+    fn find_sprite_themes(ctx: &mut Context) -> Vec<String> {
+        let mut themes: Vec<String> = match ggez::filesystem::read_dir(ctx, "/") {
+            Ok(entries) => entries
+                .filter(|entry| {
+                    ggez::filesystem::is_dir(ctx, entry)
+                        && ggez::filesystem::exists(ctx, entry.join("white_king.png"))
+                })
+                .filter_map(|entry| Some(entry.file_name()?.to_str()?.to_string()))
+                .collect(),
+            Err(err) => {
+                eprintln!("Could not look for sprite themes: {}", err);
+                Vec::new()
+            }
+        };
+        themes.sort();
+        themes.dedup();
+        themes.insert(0, DEFAULT_SPRITE_THEME.to_string());
+        themes
+    }
+
+    /// Silences or restores the sound effects.
+    fn toggle_mute(&mut self) {
+        self.preferences.muted = !self.preferences.muted;
+        self.show_volume();
+        self.preferences.save();
+    }
+
+    /// Raises or lowers the sound effect volume by `step`, unmuting them.
+    fn change_volume(&mut self, step: f32) {
+        self.preferences.volume = (self.preferences.volume + step).clamp(0.0, 1.0);
+        self.preferences.muted = false;
+        self.show_volume();
+        self.preferences.save();
+    }
+
+    /// Briefly shows the volume, or that the sound is muted.
+    fn show_volume(&mut self) {
+        let message = if self.preferences.muted {
+            "Sound muted".to_string()
+        } else {
+            format!("Volume {}%", (self.preferences.volume * 100.0).round())
+        };
+        self.show_toast(message);
+    }
+
+    /// Previews the sprite theme after the one on show, wrapping around. The
+    /// board keeps its pieces until the preview is applied.
+    fn preview_next_sprite_theme(&mut self, ctx: &mut Context) {
+        let shown = match &self.sprite_preview {
+            Some((theme, _)) => theme,
+            None => &self.preferences.sprite_theme,
+        };
+        let index = self
+            .sprite_themes
+            .iter()
+            .position(|theme| theme == shown)
+            .map_or(0, |index| (index + 1) % self.sprite_themes.len());
+        let theme = self.sprite_themes[index].clone();
+        let sprites = AppState::load_theme_sprites(ctx, &theme);
+        self.sprite_preview = Some((theme, sprites));
+    }
+
+    /// Uses the previewed sprite theme for the board from now on.
+    fn apply_sprite_preview(&mut self) {
+        if let Some((theme, sprites)) = self.sprite_preview.take() {
+            self.theme_sprites = sprites;
+            self.show_toast(format!("Pieces: {}", theme));
+            self.preferences.sprite_theme = theme;
+            self.preferences.save();
+        }
+    }
+
+    /// Loads a sound effect, going without it if the file can't be read.
+    fn load_sound(ctx: &mut Context, path: &str) -> Option<audio::Source> {
+        audio::Source::new(ctx, path)
+            .map_err(|err| eprintln!("Could not load sound {}: {}", path, err))
+            .ok()
+    }
+
+    /// Draws a piece on the screen tile (row, column), as its sprite or, without
+    /// sprites, as its letter.
+    fn draw_piece(
+        &self,
+        ctx: &mut Context,
+        piece: Piece,
+        tile: (usize, usize),
+        alpha: f32,
+    ) -> GameResult {
+        let corner = [
+            tile.1 as f32 * self.cell_size.0 as f32,
+            tile.0 as f32 * self.cell_size.1 as f32,
+        ];
+        self.draw_piece_at(ctx, piece, corner, self.cell_size.0 as f32, alpha)
+    }
+
+    /// Draws a piece in a square box `size` pixels wide whose top left corner is at `corner`.
+    fn draw_piece_at(
+        &self,
+        ctx: &mut Context,
+        piece: Piece,
+        corner: [f32; 2],
+        size: f32,
+        alpha: f32,
+    ) -> GameResult {
+        self.draw_themed_piece_at(ctx, &self.theme_sprites, piece, corner, size, alpha)
+    }
+
+    /// Draws a piece as `draw_piece_at` does, taking its image from `theme_sprites`.
+    fn draw_themed_piece_at(
+        &self,
+        ctx: &mut Context,
+        theme_sprites: &Sprites,
+        piece: Piece,
+        corner: [f32; 2],
+        size: f32,
+        alpha: f32,
+    ) -> GameResult {
+        let key = (piece.is_white, piece.role);
+        if let Some(sprite) = theme_sprites.get(&key).or_else(|| self.sprites.get(&key)) {
+            let scale = size / sprite.width() as f32;
+            return graphics::draw(
+                ctx,
+                sprite,
+                graphics::DrawParam::default()
+                    .color([1.0, 1.0, 1.0, alpha].into())
+                    .scale([scale, scale])
+                    .dest(corner),
+            );
+        }
+
+        let letter_size = size * 2.0 / 3.0;
+        let letter = graphics::Text::new(
+            graphics::TextFragment::from(role_letter(piece.role).to_string()).scale(
+                graphics::PxScale {
+                    x: letter_size,
+                    y: letter_size,
+                },
+            ),
+        );
+        let dimensions = letter.dimensions(ctx);
+        let shade = if piece.is_white { 1.0 } else { 0.0 };
+        graphics::draw(
+            ctx,
+            &letter,
+            graphics::DrawParam::default()
+                .color([shade, shade, shade, alpha].into())
+                .dest([
+                    corner[0] + (size - dimensions.w) / 2.0,
+                    corner[1] + (size - dimensions.h) / 2.0,
+                ]),
+        )
+    }
+
+    /// Draws every tile's screen bounds and square name, for spotting row/column mix-ups,
+    /// plus how many legal moves each piece of the side to move has.
+    fn draw_debug_grid(&self, ctx: &mut Context) -> GameResult {
+        for row in 0..self.board_size {
+            for col in 0..self.board_size {
+                let bounds = graphics::Rect::new(
+                    col as f32 * self.cell_size.0 as f32,
+                    row as f32 * self.cell_size.1 as f32,
+                    self.cell_size.0 as f32,
+                    self.cell_size.1 as f32,
+                );
+                let (board_row, board_col) = self.flip_square((row, col));
+                let outline = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::stroke(1.0),
+                    bounds,
+                    [1.0, 0.0, 1.0, 1.0].into(),
+                )?;
+                graphics::draw(ctx, &outline, graphics::DrawParam::default())?;
+
+                // tiles beyond the engine's board on experimental sizes have no name
+                let name = if coords::on_board((board_row, board_col)) {
+                    square_name((board_row, board_col))
+                } else {
+                    "-".to_string()
+                };
+                let label = graphics::Text::new(
+                    graphics::TextFragment::from(format!(
+                        "{}\n({}, {})\n{}x{}",
+                        name, board_row, board_col, bounds.x, bounds.y
+                    ))
+                    .scale(graphics::PxScale { x: 14.0, y: 14.0 }),
+                );
+                graphics::draw(
+                    ctx,
+                    &label,
+                    graphics::DrawParam::default()
+                        .color([1.0, 0.0, 1.0, 1.0].into())
+                        .dest([bounds.x + 3.0, bounds.y + 3.0]),
+                )?;
+
+                let friendly = self.board[board_row][board_col]
+                    .is_some_and(|piece| piece.is_white == self.game.is_white_turn());
+                if friendly {
+                    let move_count = self
+                        .game
+                        .get_possible_moves(&name)
+                        .map_or(0, |moves| moves.len());
+                    let count = graphics::Text::new(
+                        graphics::TextFragment::from(move_count.to_string())
+                            .scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+                    );
+                    let count_dimensions = count.dimensions(ctx);
+                    graphics::draw(
+                        ctx,
+                        &count,
+                        graphics::DrawParam::default()
+                            .color([1.0, 0.0, 1.0, 1.0].into())
+                            .dest([
+                                bounds.x + bounds.w - count_dimensions.w - 3.0,
+                                bounds.y + bounds.h - count_dimensions.h - 3.0,
+                            ]),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pixel position of the center of the tile at (row, column).
+    fn tile_center(&self, square: (usize, usize)) -> [f32; 2] {
+        let square = self.flip_square(square);
+        [
+            (square.1 as f32 + 0.5) * self.cell_size.0 as f32,
+            (square.0 as f32 + 0.5) * self.cell_size.1 as f32,
+        ]
+    }
+
+    /// Draws an arrow between the centers of two tiles given as (row, column).
+    fn draw_arrow(
+        &self,
+        ctx: &mut Context,
+        from: (usize, usize),
+        to: (usize, usize),
+        color: Color,
+    ) -> GameResult {
+        let start = self.tile_center(from);
+        let tip = self.tile_center(to);
+        let (dx, dy) = (tip[0] - start[0], tip[1] - start[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return Ok(());
+        }
+        let (ux, uy) = (dx / length, dy / length);
+
+        let head_length = self.cell_size.0 as f32 * 0.35;
+        let head_width = self.cell_size.0 as f32 * 0.2;
+        let base = [tip[0] - ux * head_length, tip[1] - uy * head_length];
+
+        let shaft = graphics::Mesh::new_line(ctx, &[start, base], 10.0, color)?;
+        graphics::draw(ctx, &shaft, graphics::DrawParam::default())?;
+
+        let head = graphics::Mesh::new_polygon(
+            ctx,
+            graphics::DrawMode::fill(),
+            &[
+                tip,
+                [base[0] - uy * head_width, base[1] + ux * head_width],
+                [base[0] + uy * head_width, base[1] - ux * head_width],
+            ],
+            color,
+        )?;
+        graphics::draw(ctx, &head, graphics::DrawParam::default())
+    }
+
+    /// (row, column) squares the last move went from and to.
+    fn last_move_squares(&self) -> Option<((usize, usize), (usize, usize))> {
+        let last_move = self.move_history[..self.shown_ply()].last()?;
+        Some((parse_square(&last_move.from)?, parse_square(&last_move.to)?))
+    }
+
+    /// Draws the arrow along the last move, optionally labelled with its move number.
+    fn draw_last_move_arrow(&self, ctx: &mut Context) -> GameResult {
+        let ((from_row, from_col), (to_row, to_col)) = match self.last_move_squares() {
+            Some(squares) => squares,
+            None => return Ok(()),
+        };
+        let color = self.preferences.highlight_colors().last_move.into();
+        self.draw_arrow(ctx, (from_row, from_col), (to_row, to_col), color)?;
+
+        if self.preferences.last_move_arrow_numbers {
+            let ply = self.shown_ply();
+            let number = if ply % 2 == 1 {
+                format!("{}.", ply / 2 + 1)
+            } else {
+                format!("{}...", ply / 2)
+            };
+            let label = graphics::Text::new(
+                graphics::TextFragment::from(number).scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+            );
+            let dimensions = label.dimensions(ctx);
+            let start = self.tile_center((from_row, from_col));
+            let end = self.tile_center((to_row, to_col));
+
+            graphics::draw(
+                ctx,
+                &label,
+                graphics::DrawParam::default()
+                    .color([0.0, 0.0, 0.0, 1.0].into())
+                    .dest([
+                        (start[0] + end[0] - dimensions.w) / 2.0,
+                        (start[1] + end[1] - dimensions.h) / 2.0,
+                    ]),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Works out the threats to the hovered piece unless they are already known
+    /// for this square and position.
+    fn refresh_hover_threats(&mut self) {
+        let ply = self.move_history.len();
+        let square = match self.hovered {
+            Some(square) => square,
+            None => {
+                self.hover_threats = None;
+                return;
+            }
+        };
+        if self
+            .hover_threats
+            .is_some_and(|threats| threats.square == square && threats.ply == ply)
+        {
+            return;
+        }
+
+        self.hover_threats = self.board[square.0][square.1].map(|piece| HoverThreats {
+            square,
+            ply,
+            defended: attacks::is_attacked(&self.board, square, piece.is_white),
+            attacked: attacks::is_attacked(&self.board, square, !piece.is_white),
+        });
+    }
+
+    /// Writes square coordinates into the tile corners as the preferences ask,
+    /// following the board as it is shown.
+    fn draw_coordinates(&self, ctx: &mut Context) -> GameResult {
+        let last = self.board_size - 1;
+
+        for row in 0..self.board_size {
+            for col in 0..self.board_size {
+                let (board_row, board_col) = self.flip_square((row, col));
+                let file = ((b'a' + board_col as u8) as char).to_string();
+                let rank = (self.board_size - board_row).to_string();
+                // (text, anchored to the right edge?, anchored to the bottom edge?)
+                let labels = match self.preferences.coordinates {
+                    CoordinateMode::Off => vec![],
+                    CoordinateMode::EdgeTiles => {
+                        let mut labels = vec![];
+                        if row == last {
+                            labels.push((file, true, true));
+                        }
+                        if col == 0 {
+                            labels.push((rank, false, false));
+                        }
+                        labels
+                    }
+                    CoordinateMode::EveryTile => vec![(file + &rank, false, false)],
+                };
+
+                for (text, right, bottom) in labels {
+                    let label = graphics::Text::new(
+                        graphics::TextFragment::from(text)
+                            .scale(graphics::PxScale { x: 16.0, y: 16.0 }),
+                    );
+                    let dimensions = label.dimensions(ctx);
+                    let x = if right {
+                        (col + 1) as f32 * self.cell_size.0 as f32 - dimensions.w - 4.0
+                    } else {
+                        col as f32 * self.cell_size.0 as f32 + 4.0
+                    };
+                    let y = if bottom {
+                        (row + 1) as f32 * self.cell_size.1 as f32 - dimensions.h - 2.0
+                    } else {
+                        row as f32 * self.cell_size.1 as f32 + 2.0
+                    };
+                    graphics::draw(
+                        ctx,
+                        &label,
+                        graphics::DrawParam::default()
+                            .color([0.0, 0.0, 0.0, 0.6].into())
+                            .dest([x, y]),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Dots on the hovered piece's tile: green when defended, red when attacked.
+    fn draw_hover_threats(&self, ctx: &mut Context) -> GameResult {
+        let threats = match self.hover_threats {
+            Some(threats) => threats,
+            None => return Ok(()),
+        };
+        let (row, col) = self.flip_square(threats.square);
+        let dots = [
+            (threats.defended, 0.15, DEFENDED_DOT),
+            (threats.attacked, 0.85, ATTACKED_DOT),
+        ];
+
+        for (shown, x, color) in dots {
+            if !shown {
+                continue;
+            }
+            let dot = graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::fill(),
+                [
+                    (col as f32 + x) * self.cell_size.0 as f32,
+                    (row as f32 + 0.85) * self.cell_size.1 as f32,
+                ],
+                self.cell_size.0 as f32 * 0.07,
+                0.5,
+                color,
+            )?;
+            graphics::draw(ctx, &dot, graphics::DrawParam::default())?;
+        }
+
+        Ok(())
+    }
+
+    /// Points from the selected piece to its destination when it has only one legal move.
+    fn draw_only_move_arrow(&self, ctx: &mut Context) -> GameResult {
+        if let (Some(piece), [(row, col)]) = (self.highlight_piece, self.highlight_poses.as_slice())
+        {
+            let from = (piece.position.0 as usize, piece.position.1 as usize);
+            self.draw_arrow(ctx, from, (*row, *col), ONLY_MOVE_ARROW)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the panel beside the board with the kinds of piece each side has
+    /// captured and how many of each, most valuable first, and the moves played
+    /// below them.
+    fn draw_side_panel(&self, ctx: &mut Context) -> GameResult {
+        let board = board_extent(self.board_size, self.cell_size);
+        let panel = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(board.0, 0.0, SIDE_PANEL_WIDTH, board.1),
+            [0.85, 0.85, 0.85, 1.0].into(),
+        )?;
+        graphics::draw(ctx, &panel, graphics::DrawParam::default())?;
+
+        let left = board.0 + 16.0;
+        let mut top = self.draw_clocks(ctx, [left, 16.0])? + 16.0;
+
+        let balance_text = graphics::Text::new(
+            graphics::TextFragment::from(material_balance(&self.board))
+                .scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+        );
+        graphics::draw(
+            ctx,
+            &balance_text,
+            graphics::DrawParam::default()
+                .color([0.0, 0.0, 0.0, 1.0].into())
+                .dest([left, top]),
+        )?;
+        top += balance_text.dimensions(ctx).h + 16.0;
+
+        let entry_width = CAPTURED_PIECE_SIZE + CAPTURE_COUNT_WIDTH;
+        let per_row = ((SIDE_PANEL_WIDTH - 32.0) / entry_width) as usize;
+        for (heading, by_white) in [("Captured by white", true), ("Captured by black", false)] {
+            let heading_text = graphics::Text::new(
+                graphics::TextFragment::from(heading).scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+            );
+            graphics::draw(
+                ctx,
+                &heading_text,
+                graphics::DrawParam::default()
+                    .color([0.0, 0.0, 0.0, 1.0].into())
+                    .dest([left, top]),
+            )?;
+            top += heading_text.dimensions(ctx).h + 8.0;
+
+            let mut taken: Vec<(u8, usize)> = self
+                .captured
+                .iter()
+                .filter(|((is_white, _), _)| *is_white != by_white)
+                .map(|(&(_, role), &count)| (role, count))
+                .collect();
+            taken.sort_by_key(|(role, _)| std::cmp::Reverse(role_value(*role)));
+            for (index, (role, count)) in taken.iter().enumerate() {
+                let corner = [
+                    left + (index % per_row) as f32 * entry_width,
+                    top + (index / per_row) as f32 * CAPTURED_PIECE_SIZE,
+                ];
+                let piece = Piece::new(*role, (0, 0), !by_white);
+                self.draw_piece_at(ctx, piece, corner, CAPTURED_PIECE_SIZE, 1.0)?;
+
+                let count_text = graphics::Text::new(
+                    graphics::TextFragment::from(format!("x{}", count))
+                        .scale(graphics::PxScale { x: 16.0, y: 16.0 }),
+                );
+                let text_dimensions = count_text.dimensions(ctx);
+                graphics::draw(
+                    ctx,
+                    &count_text,
+                    graphics::DrawParam::default()
+                        .color([0.0, 0.0, 0.0, 1.0].into())
+                        .dest([
+                            corner[0] + CAPTURED_PIECE_SIZE + 2.0,
+                            corner[1] + (CAPTURED_PIECE_SIZE - text_dimensions.h) / 2.0,
+                        ]),
+                )?;
+            }
+            let rows = taken.len().div_ceil(per_row).max(1);
+            top += rows as f32 * CAPTURED_PIECE_SIZE + 16.0;
+        }
+
+        self.draw_move_list(ctx, [left, top], board.1 - top - 16.0)
+    }
+
+    /// Draws both clocks side by side from `corner`, the running one lit up and
+    /// enlarged if the preferences ask for it, and returns where they end.
+    fn draw_clocks(&self, ctx: &mut Context, corner: [f32; 2]) -> GameResult<f32> {
+        let width = (SIDE_PANEL_WIDTH - 40.0) / 2.0;
+        let height = 32.0;
+        let white_to_move = self.game.is_white_turn();
+
+        for (index, (name, time, is_white)) in [
+            ("White", self.white_time, true),
+            ("Black", self.black_time, false),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let bounds = graphics::Rect::new(
+                corner[0] + index as f32 * (width + 8.0),
+                corner[1],
+                width,
+                height,
+            );
+            let running = self.clock_running() && is_white == white_to_move;
+            let (shade, text_size) = match (self.preferences.emphasize_active_clock, running) {
+                (false, _) => (0.9, 16.0),
+                (true, true) => (1.0, 20.0),
+                (true, false) => (0.6, 14.0),
+            };
+            let background = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                bounds,
+                [shade, shade, shade, 1.0].into(),
+            )?;
+            graphics::draw(ctx, &background, graphics::DrawParam::default())?;
+
+            let clock_text = graphics::Text::new(
+                graphics::TextFragment::from(format!("{} {}", name, clock_face(time))).scale(
+                    graphics::PxScale {
+                        x: text_size,
+                        y: text_size,
+                    },
+                ),
+            );
+            let text_dimensions = clock_text.dimensions(ctx);
+            graphics::draw(
+                ctx,
+                &clock_text,
+                graphics::DrawParam::default()
+                    .color([0.0, 0.0, 0.0, 1.0].into())
+                    .dest([
+                        bounds.x + (bounds.w - text_dimensions.w) / 2.0,
+                        bounds.y + (bounds.h - text_dimensions.h) / 2.0,
+                    ]),
+            )?;
+        }
+
+        Ok(corner[1] + height)
+    }
+
+    /// Lines of the move list, pairing white's and black's moves: "1. e4 e5".
+    fn move_list_lines(&self) -> Vec<String> {
+        self.move_log
+            .chunks(2)
+            .enumerate()
+            .map(|(index, pair)| format!("{}. {}", index + 1, pair.join(" ")))
+            .collect()
+    }
+
+    /// Draws as much of the move list as fits in `height` pixels below `corner`,
+    /// ending `move_log_scroll` lines before the latest move.
+    fn draw_move_list(&self, ctx: &mut Context, corner: [f32; 2], height: f32) -> GameResult {
+        let lines = self.move_list_lines();
+        let visible = (height / MOVE_LIST_LINE_HEIGHT).max(0.0) as usize;
+        let scroll = self
+            .move_log_scroll
+            .min(lines.len().saturating_sub(visible));
+        let end = lines.len() - scroll;
+        let start = end.saturating_sub(visible);
+
+        for (index, line) in lines[start..end].iter().enumerate() {
+            let line_text = graphics::Text::new(
+                graphics::TextFragment::from(line.as_str())
+                    .scale(graphics::PxScale { x: 18.0, y: 18.0 }),
+            );
+            graphics::draw(
+                ctx,
+                &line_text,
+                graphics::DrawParam::default()
+                    .color([0.0, 0.0, 0.0, 1.0].into())
+                    .dest([corner[0], corner[1] + index as f32 * MOVE_LIST_LINE_HEIGHT]),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Dims the board and shows a question in the middle of it.
+    fn draw_prompt(&self, ctx: &mut Context, message: &str) -> GameResult {
+        let screen = screen_size(self.board_size, self.cell_size);
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, screen.0, screen.1),
+            [0.0, 0.0, 0.0, 0.5].into(),
+        )?;
+        graphics::draw(ctx, &shade, graphics::DrawParam::default())?;
+
+        let prompt_text = graphics::Text::new(
+            graphics::TextFragment::from(message).scale(graphics::PxScale { x: 30.0, y: 30.0 }),
+        );
+        let text_dimensions = prompt_text.dimensions(ctx);
+        let board = board_extent(self.board_size, self.cell_size);
+        let position = [
+            (board.0 - text_dimensions.w) / 2.0,
+            (board.1 - text_dimensions.h) / 2.0,
+        ];
+
+        let background_box = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(
+                position[0] - 16.0,
+                position[1] - 8.0,
+                text_dimensions.w + 32.0,
+                text_dimensions.h + 16.0,
+            ),
+            [1.0, 1.0, 1.0, 1.0].into(),
+        )?;
+        graphics::draw(ctx, &background_box, graphics::DrawParam::default())?;
+        graphics::draw(
+            ctx,
+            &prompt_text,
+            graphics::DrawParam::default()
+                .color([0.0, 0.0, 0.0, 1.0].into())
+                .dest(position),
+        )
+    }
+
+    /// Draws a message in a strip along the top of the board.
+    fn draw_banner(&self, ctx: &mut Context, message: &str) -> GameResult {
+        let board = board_extent(self.board_size, self.cell_size);
+        let banner_text = graphics::Text::new(
+            graphics::TextFragment::from(message).scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+        );
+        let text_dimensions = banner_text.dimensions(ctx);
+        let height = text_dimensions.h + 12.0;
+
+        let background_box = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, board.0, height),
+            [0.15, 0.25, 0.45, 0.9].into(),
+        )?;
+        graphics::draw(ctx, &background_box, graphics::DrawParam::default())?;
+        graphics::draw(
+            ctx,
+            &banner_text,
+            graphics::DrawParam::default()
+                .color([1.0, 1.0, 1.0, 1.0].into())
+                .dest([(board.0 - text_dimensions.w) / 2.0, 6.0]),
+        )
+    }
+
+    /// Draws the open command line across the bottom of the board, with the
+    /// reason the last command was refused after it.
+    fn draw_command_line(&self, ctx: &mut Context) -> GameResult {
+        let command = match &self.command_buffer {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let board = board_extent(self.board_size, self.cell_size);
+        let scale = graphics::PxScale { x: 20.0, y: 20.0 };
+        let mut line_text = graphics::Text::new(
+            graphics::TextFragment::from(format!(":{}_", command)).scale(scale),
+        );
+        if let Some(error) = &self.command_error {
+            line_text.add(
+                graphics::TextFragment::from(format!("   {}", error))
+                    .scale(scale)
+                    .color(graphics::Color::from_rgb(255, 120, 100)),
+            );
+        }
+        let text_dimensions = line_text.dimensions(ctx);
+        let height = text_dimensions.h + 12.0;
+
+        let background_box = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, board.1 - height, board.0, height),
+            [0.1, 0.1, 0.1, 0.9].into(),
+        )?;
+        graphics::draw(ctx, &background_box, graphics::DrawParam::default())?;
+        graphics::draw(
+            ctx,
+            &line_text,
+            graphics::DrawParam::default()
+                .color([1.0, 1.0, 1.0, 1.0].into())
+                .dest([8.0, board.1 - height + 6.0]),
+        )
+    }
+
+    /// Draws the current toast message, if any, centered at the bottom of the board.
+    fn draw_toast(&self, ctx: &mut Context) -> GameResult {
+        let board = board_extent(self.board_size, self.cell_size);
+        let message = match &self.toast {
+            Some((message, shown_at)) if shown_at.elapsed() < TOAST_DURATION => message,
+            _ => return Ok(()),
+        };
+
+        let toast_text = graphics::Text::new(
+            graphics::TextFragment::from(message.as_str())
+                .scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+        );
+        let text_dimensions = toast_text.dimensions(ctx);
+        let position = [
+            (board.0 - text_dimensions.w) / 2.0,
+            board.1 - text_dimensions.h - 16.0,
+        ];
+
+        let background_box = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(
+                position[0] - 8.0,
+                position[1] - 4.0,
+                text_dimensions.w + 16.0,
+                text_dimensions.h + 8.0,
+            ),
+            [1.0, 1.0, 1.0, 0.9].into(),
+        )?;
+        graphics::draw(ctx, &background_box, graphics::DrawParam::default())?;
+        graphics::draw(
+            ctx,
+            &toast_text,
+            graphics::DrawParam::default()
+                .color([0.0, 0.0, 0.0, 1.0].into())
+                .dest(position),
+        )
+    }
+}
+
+// This is where we implement the functions that ggez requires to function
+impl event::EventHandler<GameError> for AppState {
+    /// For updating game logic, which front-end doesn't handle.
+    /// It won't be necessary to touch this unless you are implementing something that's not triggered by the user, like a clock
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        // moves and endings coming from here rather than from input need drawing too
+        let plies = self.move_history.len();
+        let ended = self.game_over.is_some();
+
+        // the elapsed time in the status text ticks over every second until the game ends
+        let elapsed = self.elapsed();
+        if self.game_length.is_none()
+            && elapsed.as_secs() != elapsed.saturating_sub(ggez::timer::delta(ctx)).as_secs()
+        {
+            self.dirty = true;
+        }
+
+        // the resign prompt goes away when R is not pressed again in time
+        if self
+            .pending_resign
+            .is_some_and(|pressed| pressed.elapsed() >= RESIGN_CONFIRM_WINDOW)
+        {
+            self.pending_resign = None;
+            self.dirty = true;
+        }
+
+        if self.clock_running() {
+            let white = self.game.is_white_turn();
+            let time = if white {
+                &mut self.white_time
+            } else {
+                &mut self.black_time
+            };
+            let shown_seconds = time.as_secs();
+            *time = time.saturating_sub(ggez::timer::delta(ctx));
+            if time.as_secs() != shown_seconds {
+                self.dirty = true;
+            }
+            if time.is_zero() {
+                self.flag_fall(white);
+            }
+        }
+
+        // the computer opponent thinks for a moment before moving
+        // the game waits while earlier moves are being looked at
+        if self.ai_to_move()
+            && self.review.is_none()
+            && self.game.get_game_state() != GameState::GameOver
+        {
+            match self.ai_move_due {
+                None => self.ai_move_due = Some(Instant::now() + AI_MOVE_DELAY),
+                Some(due) if Instant::now() >= due => {
+                    self.ai_move_due = None;
+                    self.load_board();
+                    self.play_ai_move();
+                }
+                Some(_) => (),
+            }
+        }
+
+        // the player's queued move goes in as soon as it is their turn
+        if !self.ai_to_move() && self.game_over.is_none() && self.review.is_none() {
+            if let Some(premove) = self.premove.take() {
+                self.play_premove(premove);
+            }
+        }
 
-                    // println!("Filerank: {}", file_rank);
-                    let moves = self.game.get_possible_moves(&file_rank);
+        if self.game_over.is_none() && self.game.get_game_state() == GameState::GameOver {
+            // no legal moves left: checkmate if the side to move is in check, otherwise stalemate
+            self.load_board();
+            let white_to_move = self.game.is_white_turn();
+            let checkmate = attacks::in_check(&self.board, white_to_move);
+            self.play_game_over_sound(checkmate);
+            if !checkmate {
+                self.end_game("1/2-1/2", "Stalemate, the game is drawn");
+            } else if white_to_move {
+                self.end_game("0-1", "Checkmate, black wins");
+            } else {
+                self.end_game("1-0", "Checkmate, white wins");
+            }
+        }
 
-                    if !moves.is_none() {
-                        self.highlight_poses = self.to_tuple_moves(moves.unwrap());
-                        self.highlight_piece = self.board[board_column][board_row];
-                        // TODO: convert it to positions I can use
+        if self.move_history.len() != plies || self.game_over.is_some() != ended {
+            self.dirty = true;
+        }
+
+        // hotseat games turn the board to whoever is on move
+        if self.preferences.auto_flip {
+            self.flipped = !self.game.is_white_turn();
+        }
+
+        let title = self.title();
+        if title != self.window_title {
+            graphics::set_window_title(ctx, &title);
+            self.window_title = title;
+        }
+        Ok(())
+    }
+
+    /// Draw interface, i.e. draw game board
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        // the last frame stays on screen until something changes
+        if !self.dirty {
+            ggez::timer::sleep(IDLE_FRAME_SLEEP);
+            return Ok(());
+        }
+        let board = board_extent(self.board_size, self.cell_size);
+        self.load_board();
+
+        // clear interface with gray background colour
+        graphics::clear(ctx, [0.5, 0.5, 0.5, 1.0].into());
+
+        // create text representation
+        let state_text = graphics::Text::new(
+            graphics::TextFragment::from(self.status_text())
+                .scale(graphics::PxScale { x: 30.0, y: 30.0 }),
+        );
+
+        // get size of text
+        let text_dimensions = state_text.dimensions(ctx);
+
+        // the selected piece's highlights fade in, starting over with each new selection
+        let selected = self.highlight_piece.map(|piece| piece.position);
+        if self.selection_fade.map(|(position, _)| position) != selected {
+            self.selection_fade = selected.map(|position| (position, Instant::now()));
+        }
+        let fade = self.selection_alpha();
+        let mut colors = self.preferences.highlight_colors();
+        for tint in [
+            &mut colors.selection,
+            &mut colors.castling_rook,
+            &mut colors.attack,
+        ] {
+            // tile tints are added onto the tile, so they fade by darkening,
+            // while high-contrast tiles are drawn over it
+            if self.preferences.high_contrast {
+                tint[3] *= fade;
+            } else {
+                tint[..3]
+                    .iter_mut()
+                    .for_each(|component| *component *= fade);
+            }
+        }
+        colors.legal_move[3] *= fade;
+        colors.capture[3] *= fade;
+        let theme = self.theme();
+        let last_move = self.last_move_squares();
+        let white_to_move = match &self.review {
+            Some((ply, _)) => ply % 2 == 0,
+            None => self.game.is_white_turn(),
+        };
+        let check_square = Some(white_to_move)
+            .filter(|is_white| attacks::in_check(&self.board, *is_white))
+            .and_then(|is_white| attacks::king_square(&self.board, is_white));
+        // a king in check with no moves left is mated, and crossed out until the next game
+        let mated_king = check_square
+            .filter(|_| self.review.is_none() && self.game.get_game_state() == GameState::GameOver);
+        // every square the selected piece attacks, even those held by its own side
+        let attacked = match self.highlight_piece.filter(|_| self.show_hints) {
+            Some(piece) => attacks::attacked_squares(
+                &self.board,
+                (piece.position.0 as usize, piece.position.1 as usize),
+            ),
+            None => Vec::new(),
+        };
+        let castling_rooks = if self.show_hints {
+            self.castling_rooks()
+        } else {
+            Vec::new()
+        };
+
+        // draw grid: the checkerboard is built once per theme, and only
+        // highlighted tiles are drawn again over it
+        if self.board_mesh.0 != theme.name {
+            self.board_mesh = (
+                theme.name,
+                AppState::build_board_mesh(ctx, self.board_size, self.cell_size, &theme)?,
+            );
+        }
+        graphics::draw(ctx, &self.board_mesh.1, graphics::DrawParam::default())?;
+        for row in 0..self.board_size as i32 {
+            for col in 0..self.board_size as i32 {
+                let (board_row, board_col) = self.flip_square((row as usize, col as usize));
+                let highlight = if check_square == Some((board_row, board_col)) {
+                    Some(colors.check)
+                } else if self
+                    .highlight_piece
+                    .is_some_and(|piece| piece.position == (board_row as i16, board_col as i16))
+                    || self.premove_from == Some((board_row, board_col))
+                {
+                    Some(colors.selection)
+                } else if castling_rooks.contains(&(board_row, board_col)) {
+                    Some(colors.castling_rook)
+                } else if attacked.contains(&(board_row, board_col)) {
+                    Some(colors.attack)
+                } else if last_move.is_some_and(|(from, to)| {
+                    from == (board_row, board_col) || to == (board_row, board_col)
+                }) {
+                    Some(colors.last_move_tiles)
+                } else if self.hovered == Some((board_row, board_col)) {
+                    Some(colors.hover)
+                } else {
+                    None
+                };
+                if let Some(highlight) = highlight {
+                    let tile = graphics::Rect::new_i32(
+                        col * self.cell_size.0 as i32,
+                        row * self.cell_size.1 as i32,
+                        self.cell_size.0 as i32,
+                        self.cell_size.1 as i32,
+                    );
+                    // highlights are added onto the tile's own colour, except
+                    // high-contrast ones, which replace it and get an outline
+                    let tile_color = if self.preferences.high_contrast {
+                        highlight.into()
+                    } else if (row + col) % 2 == 0 {
+                        AppState::add_color(theme.light, highlight.into())
+                    } else {
+                        AppState::add_color(theme.dark, highlight.into())
+                    };
+                    let rectangle = graphics::Mesh::new_rectangle(
+                        ctx,
+                        graphics::DrawMode::fill(),
+                        tile,
+                        tile_color,
+                    )?;
+                    graphics::draw(ctx, &rectangle, graphics::DrawParam::default())?;
+                    if self.preferences.high_contrast {
+                        let outline = graphics::Mesh::new_rectangle(
+                            ctx,
+                            graphics::DrawMode::stroke(4.0),
+                            tile,
+                            [0.0, 0.0, 0.0, 1.0].into(),
+                        )?;
+                        graphics::draw(ctx, &outline, graphics::DrawParam::default())?;
                     }
                 }
 
-                for item in &self.highlight_poses {
-                    println!("can move to {}, {}", item.0, item.1);
+                // fade out a white flash where a piece was just captured
+                if let Some(alpha) = self.capture_flash_alpha((board_row, board_col)) {
+                    let flash = graphics::Mesh::new_rectangle(
+                        ctx,
+                        graphics::DrawMode::fill(),
+                        graphics::Rect::new_i32(
+                            col * self.cell_size.0 as i32,
+                            row * self.cell_size.1 as i32,
+                            self.cell_size.0 as i32,
+                            self.cell_size.1 as i32,
+                        ),
+                        [1.0, 1.0, 1.0, alpha].into(),
+                    )?;
+                    graphics::draw(ctx, &flash, graphics::DrawParam::default())?;
+                }
+
+                if let Some(alpha) = self.error_flash_alpha((board_row, board_col)) {
+                    let mut flash_color = ERROR_FLASH;
+                    flash_color.a = alpha;
+                    let flash = graphics::Mesh::new_rectangle(
+                        ctx,
+                        graphics::DrawMode::fill(),
+                        graphics::Rect::new_i32(
+                            col * self.cell_size.0 as i32,
+                            row * self.cell_size.1 as i32,
+                            self.cell_size.0 as i32,
+                            self.cell_size.1 as i32,
+                        ),
+                        flash_color,
+                    )?;
+                    graphics::draw(ctx, &flash, graphics::DrawParam::default())?;
+                }
+
+                if self.marked_squares.contains(&(board_row, board_col)) {
+                    let marker = graphics::Mesh::new_rectangle(
+                        ctx,
+                        graphics::DrawMode::fill(),
+                        graphics::Rect::new_i32(
+                            col * self.cell_size.0 as i32,
+                            row * self.cell_size.1 as i32,
+                            self.cell_size.0 as i32,
+                            self.cell_size.1 as i32,
+                        ),
+                        ANNOTATION_MARKER,
+                    )?;
+                    graphics::draw(ctx, &marker, graphics::DrawParam::default())?;
+                }
+
+                // legal destinations: a dot on empty tiles, a ring around pieces to
+                // capture and around the empty tile an en passant capture lands on
+                if self.show_hints && self.highlight_poses.contains(&(board_row, board_col)) {
+                    let center = [
+                        (col as f32 + 0.5) * self.cell_size.0 as f32,
+                        (row as f32 + 0.5) * self.cell_size.1 as f32,
+                    ];
+                    let en_passant = self.is_en_passant((board_row, board_col));
+                    let captures = self.board[board_row][board_col].is_some() || en_passant;
+                    let marker = if captures {
+                        graphics::Mesh::new_circle(
+                            ctx,
+                            graphics::DrawMode::stroke(self.cell_size.0 as f32 * 0.08),
+                            center,
+                            self.cell_size.0 as f32 * 0.44,
+                            0.5,
+                            colors.capture.into(),
+                        )?
+                    } else {
+                        graphics::Mesh::new_circle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            center,
+                            self.cell_size.0 as f32 * 0.15,
+                            0.5,
+                            colors.legal_move.into(),
+                        )?
+                    };
+                    graphics::draw(ctx, &marker, graphics::DrawParam::default())?;
+
+                    if en_passant {
+                        let label = graphics::Text::new(
+                            graphics::TextFragment::from("e.p.")
+                                .scale(graphics::PxScale { x: 20.0, y: 20.0 }),
+                        );
+                        let dimensions = label.dimensions(ctx);
+                        let mut label_color = colors.capture;
+                        label_color[3] = 1.0;
+                        graphics::draw(
+                            ctx,
+                            &label,
+                            graphics::DrawParam::default()
+                                .color(label_color.into())
+                                .dest([
+                                    center[0] - dimensions.w / 2.0,
+                                    center[1] - dimensions.h / 2.0,
+                                ]),
+                        )?;
+                    }
+                }
+
+                // draw piece, leaving the tile of a dragged piece empty, as well as
+                // the tile a moved piece is still sliding to
+                let dragged = self.dragging == Some((board_row, board_col));
+                let sliding = self.slide_progress().is_some()
+                    && self.slide.as_ref().is_some_and(|(slides, _)| {
+                        slides.iter().any(|&(_, to)| to == (board_row, board_col))
+                    });
+                if let Some(piece) =
+                    self.board[board_row][board_col].filter(|_| !dragged && !sliding)
+                {
+                    self.draw_piece(ctx, piece, (row as usize, col as usize), 1.0)
+                        .expect("Failed to draw piece.");
+
+                    if self.preferences.promotion_markers
+                        && self.promoted.contains(&(board_row, board_col))
+                    {
+                        let marker = graphics::Mesh::new_circle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            [
+                                (col as f32 + 0.85) * self.cell_size.0 as f32,
+                                (row as f32 + 0.15) * self.cell_size.1 as f32,
+                            ],
+                            self.cell_size.0 as f32 * 0.07,
+                            0.5,
+                            PROMOTION_MARKER,
+                        )?;
+                        graphics::draw(ctx, &marker, graphics::DrawParam::default())?;
+                    }
+                }
+
+                if mated_king == Some((board_row, board_col)) {
+                    let corner = |x: f32, y: f32| {
+                        [
+                            (col as f32 + x) * self.cell_size.0 as f32,
+                            (row as f32 + y) * self.cell_size.1 as f32,
+                        ]
+                    };
+                    let width = self.cell_size.0 as f32 * 0.1;
+                    for stroke in [
+                        [corner(0.2, 0.2), corner(0.8, 0.8)],
+                        [corner(0.8, 0.2), corner(0.2, 0.8)],
+                    ] {
+                        let line = graphics::Mesh::new_line(ctx, &stroke, width, CHECKMATE_MARKER)?;
+                        graphics::draw(ctx, &line, graphics::DrawParam::default())?;
+                    }
                 }
             }
-            /* check click position and update board accordingly */
         }
+
+        if let Some(cursor) = self.keyboard_cursor {
+            let tile = self.flip_square(cursor);
+            let outline = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::stroke(self.cell_size.0 as f32 * 0.06),
+                graphics::Rect::new_i32(
+                    tile.1 as i32 * self.cell_size.0 as i32,
+                    tile.0 as i32 * self.cell_size.1 as i32,
+                    self.cell_size.0 as i32,
+                    self.cell_size.1 as i32,
+                ),
+                KEYBOARD_CURSOR,
+            )?;
+            graphics::draw(ctx, &outline, graphics::DrawParam::default())?;
+        }
+
+        // the last moved pieces slide over the board from where they came from
+        if let (Some((slides, _)), Some(progress)) = (self.slide.clone(), self.slide_progress()) {
+            for (from, to) in slides {
+                if let Some(piece) = self.board[to.0][to.1] {
+                    let (from_tile, to_tile) = (self.flip_square(from), self.flip_square(to));
+                    let lerp = |start: usize, end: usize| {
+                        start as f32 + (end as f32 - start as f32) * progress
+                    };
+                    let corner = [
+                        lerp(from_tile.1, to_tile.1) * self.cell_size.0 as f32,
+                        lerp(from_tile.0, to_tile.0) * self.cell_size.1 as f32,
+                    ];
+                    self.draw_piece_at(ctx, piece, corner, self.cell_size.0 as f32, 1.0)?;
+                }
+            }
+        }
+
+        // ghost of the piece on the destination waiting for confirmation
+        if let (Some(piece), Some(square)) = (self.highlight_piece, self.pending_move) {
+            self.draw_piece(ctx, piece, self.flip_square(square), 0.5)?;
+        }
+
+        // a see-through copy of a dragged piece follows the mouse, centred on it
+        if let Some((row, col)) = self.dragging {
+            if let Some(piece) = self.board[row][col] {
+                let size = self.cell_size.0 as f32;
+                let corner = [self.cursor[0] - size / 2.0, self.cursor[1] - size / 2.0];
+                self.draw_piece_at(ctx, piece, corner, size, 0.7)?;
+            }
+        }
+
+        self.draw_coordinates(ctx)?;
+        self.draw_side_panel(ctx)?;
+
+        if self.preferences.last_move_arrow {
+            self.draw_last_move_arrow(ctx)?;
+        }
+
+        if self.preferences.only_move_arrow && self.show_hints {
+            self.draw_only_move_arrow(ctx)?;
+        }
+
+        if let Some(premove) = &self.premove {
+            if let (Some(from), Some(to)) = (parse_square(&premove.from), parse_square(&premove.to))
+            {
+                self.draw_arrow(ctx, from, to, PREMOVE_ARROW)?;
+            }
+        }
+
+        for &(from, to) in &self.annotations {
+            self.draw_arrow(ctx, from, to, ANNOTATION_ARROW)?;
+        }
+
+        if self.preferences.teaching_mode {
+            self.refresh_hover_threats();
+            self.draw_hover_threats(ctx)?;
+        }
+
+        if self.debug_grid {
+            self.draw_debug_grid(ctx)?;
+        }
+
+        // draw text with dark gray colouring and center position
+        graphics::draw(
+            ctx,
+            &state_text,
+            graphics::DrawParam::default()
+                .color([0.0, 0.0, 0.0, 1.0].into())
+                .dest(ggez::mint::Point2 {
+                    x: (board.0 - text_dimensions.w as f32) / 2f32 as f32,
+                    y: (board.0 - text_dimensions.h as f32) / 2f32 as f32,
+                }),
+        )
+        .expect("Failed to draw text.");
+
+        self.draw_promotion_dialog(ctx)?;
+        self.draw_sprite_preview(ctx)?;
+
+        if self.pending_resign.is_some() {
+            self.draw_prompt(ctx, "Resign? Press R again or Y, N to cancel")?;
+        }
+
+        if let Some((ply, _)) = self.review {
+            let message = format!(
+                "Reviewing move {} of {}: Left/Right to step, Down to return",
+                ply,
+                self.move_history.len()
+            );
+            self.draw_banner(ctx, &message)?;
+        } else if self.threefold_repetition() && self.game_over.is_none() {
+            self.draw_banner(ctx, "Threefold repetition — draw available")?;
+        } else if let Some(reason) = &self.game_over {
+            self.draw_prompt(ctx, &format!("{}. Press N for a new game", reason))?;
+        }
+
+        self.draw_toast(ctx)?;
+        self.draw_command_line(ctx)?;
+
+        // taken before presenting, while the frame is still the one drawn
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.save_screenshot(ctx);
+        }
+
+        // render updated graphics
+        graphics::present(ctx).expect("Failed to update graphics.");
+        // one more frame once an animation ends leaves it in its final state
+        self.dirty = self.animating();
+
+        Ok(())
+    }
+
+    /// Track the square under the mouse for the hover highlight and teaching aids
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        let [x, y] = window_to_screen(ctx, x, y);
+        self.cursor = [x, y];
+        let tile = (
+            (y / self.cell_size.1 as f32) as usize,
+            (x / self.cell_size.0 as f32) as usize,
+        );
+        let hovered =
+            if x >= 0.0 && y >= 0.0 && tile.0 < self.board_size && tile.1 < self.board_size {
+                Some(self.flip_square(tile))
+            } else {
+                None
+            };
+        // a dragged piece follows the cursor, everything else only the hovered tile
+        if hovered != self.hovered || self.dragging.is_some() {
+            self.dirty = true;
+        }
+        self.hovered = hovered;
+    }
+
+    /// Forget the hovered square once the mouse leaves the window
+    fn mouse_enter_or_leave(&mut self, _ctx: &mut Context, entered: bool) {
+        if !entered {
+            self.hovered = None;
+            self.dirty = true;
+        }
+    }
+
+    /// Scroll the move list
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        self.dirty = true;
+        let lines = self.move_log.len().div_ceil(2);
+        if y > 0.0 {
+            self.move_log_scroll = (self.move_log_scroll + 1).min(lines);
+        } else if y < 0.0 {
+            self.move_log_scroll = self.move_log_scroll.saturating_sub(1);
+        }
+    }
+
+    /// Pick up a piece of the side to move to drag it
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) {
+        let [x, y] = window_to_screen(ctx, x, y);
+        self.dirty = true;
+        if button == self.secondary_button() {
+            self.annotation_start = self.square_at(x, y);
+            return;
+        }
+        if button != self.primary_button()
+            || self.presenting
+            || self.pending_resign.is_some()
+            || self.game_over.is_some()
+            || self.ai_to_move()
+            || self.pending_promotion.is_some()
+            || self.fixed_position.is_some()
+            || self.review.is_some()
+        {
+            return;
+        }
+        let square = match self.square_at(x, y) {
+            Some(square) => square,
+            None => return,
+        };
+        let piece = match self.board[square.0][square.1] {
+            Some(piece) if piece.is_white == self.game.is_white_turn() => piece,
+            _ => return,
+        };
+
+        if let Some(moves) = self.game.get_possible_moves(&square_name(square)) {
+            self.highlight_poses = coords::parse_squares(&moves);
+            self.highlight_piece = Some(piece);
+            self.dragging = Some(square);
+        }
+    }
+
+    /// Update game on mouse click
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) {
+        let [x, y] = window_to_screen(ctx, x, y);
+        self.dirty = true;
+        self.crash_reporter
+            .record_click(format!("{:?} at ({:.0}, {:.0})", button, x, y));
+        let dragged_from = self
+            .dragging
+            .take()
+            .filter(|_| button == self.primary_button());
+
+        // dragging the secondary button draws an arrow, clicking it marks a square
+        if button == self.secondary_button() {
+            if let (Some(from), Some(to)) = (self.annotation_start.take(), self.square_at(x, y)) {
+                self.toggle_annotation(from, to);
+            }
+        }
+
+        self.click(button, x, y, dragged_from);
+    }
+
+    /// Handle hotkeys
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: event::KeyCode,
+        keymods: event::KeyMods,
+        _repeat: bool,
+    ) {
+        self.dirty = true;
+        // while the command line is open, keys only edit it; the typed
+        // characters themselves arrive through text_input_event
+        if let Some(command) = &mut self.command_buffer {
+            match keycode {
+                event::KeyCode::Escape => {
+                    self.command_buffer = None;
+                    self.command_error = None;
+                }
+                event::KeyCode::Back => {
+                    command.pop();
+                    self.command_error = None;
+                }
+                event::KeyCode::Return => self.submit_command(),
+                _ => (),
+            }
+            return;
+        }
+
+        // a second R or a Y within the confirmation window resigns
+        if self.pending_resign.is_some() {
+            match keycode {
+                event::KeyCode::Y | event::KeyCode::R => self.resign(),
+                event::KeyCode::N | event::KeyCode::Escape => self.pending_resign = None,
+                _ => (),
+            }
+            return;
+        }
+
+        if self.pending_promotion.is_some() {
+            if keycode == event::KeyCode::Escape {
+                self.cancel_promotion();
+            }
+            return;
+        }
+
+        // a sprite theme on preview waits to be applied or cancelled
+        if self.sprite_preview.is_some() {
+            match keycode {
+                event::KeyCode::T if keymods.contains(event::KeyMods::SHIFT) => {
+                    self.preview_next_sprite_theme(ctx)
+                }
+                event::KeyCode::Return => self.apply_sprite_preview(),
+                event::KeyCode::Escape => self.sprite_preview = None,
+                _ => (),
+            }
+            return;
+        }
+
+        // Shift + piece letter cycles through the pieces of that kind that can move
+        if keymods.contains(event::KeyMods::SHIFT) {
+            let role = match keycode {
+                event::KeyCode::K => Some(KING),
+                event::KeyCode::Q => Some(QUEEN),
+                event::KeyCode::R => Some(ROOK),
+                event::KeyCode::B => Some(BISHOP),
+                event::KeyCode::N => Some(KNIGHT),
+                event::KeyCode::P => Some(PAWN),
+                _ => None,
+            };
+            if let Some(role) = role {
+                self.quick_select(role);
+                return;
+            }
+        }
+
+        match keycode {
+            // Escape lets go of the selected piece, then hides the keyboard cursor,
+            // then wipes the annotations, and quits once there is nothing left
+            event::KeyCode::Escape
+                if self.highlight_piece.is_some() || self.pending_move.is_some() =>
+            {
+                self.clear_selection()
+            }
+            event::KeyCode::Escape if self.keyboard_cursor.is_some() => self.keyboard_cursor = None,
+            event::KeyCode::Escape
+                if !self.annotations.is_empty() || !self.marked_squares.is_empty() =>
+            {
+                self.clear_annotations()
+            }
+            event::KeyCode::Escape => {
+                self.shut_down();
+                event::quit(ctx);
+            }
+            // a single press might be a slip, so resigning takes a second one
+            event::KeyCode::R if self.game_over.is_none() => {
+                self.pending_resign = Some(Instant::now())
+            }
+            event::KeyCode::E => self.export_fen(),
+            event::KeyCode::S => self.save_game(),
+            event::KeyCode::L => self.load_game(),
+            event::KeyCode::U => self.undo(),
+            // the arrow keys move the keyboard cursor while it is showing, and
+            // step through the game otherwise
+            event::KeyCode::Left if self.keyboard_cursor.is_some() => self.move_cursor(0, -1),
+            event::KeyCode::Right if self.keyboard_cursor.is_some() => self.move_cursor(0, 1),
+            event::KeyCode::Up if self.keyboard_cursor.is_some() => self.move_cursor(-1, 0),
+            event::KeyCode::Down if self.keyboard_cursor.is_some() => self.move_cursor(1, 0),
+            event::KeyCode::Return => self.press_cursor(),
+            event::KeyCode::Left => self.review_at(self.shown_ply().saturating_sub(1)),
+            event::KeyCode::Right => self.review_at(self.shown_ply() + 1),
+            event::KeyCode::Up => self.review_at(0),
+            event::KeyCode::Down => self.review_at(self.move_history.len()),
+            event::KeyCode::N => {
+                self.new_game();
+                self.load_board();
+            }
+            event::KeyCode::C if keymods.contains(event::KeyMods::CTRL) => self.copy_fen(),
+            event::KeyCode::C => {
+                self.preferences.coordinates = self.preferences.coordinates.next();
+                self.preferences.save();
+            }
+            event::KeyCode::P if keymods.contains(event::KeyMods::CTRL) => {
+                self.screenshot_requested = true
+            }
+            event::KeyCode::P => self.toggle_presenting(ctx),
+            event::KeyCode::T if keymods.contains(event::KeyMods::SHIFT) => {
+                self.preview_next_sprite_theme(ctx)
+            }
+            event::KeyCode::T => self.next_theme(),
+            event::KeyCode::A => self.cycle_ai(),
+            event::KeyCode::F if keymods.contains(event::KeyMods::SHIFT) => {
+                self.preferences.auto_flip = !self.preferences.auto_flip;
+                self.preferences.save();
+            }
+            event::KeyCode::F => self.flipped = !self.flipped,
+            event::KeyCode::H => self.show_hints = !self.show_hints,
+            event::KeyCode::M => self.toggle_mute(),
+            event::KeyCode::O => {
+                self.preferences.emphasize_active_clock = !self.preferences.emphasize_active_clock;
+                self.preferences.save();
+            }
+            event::KeyCode::Equals | event::KeyCode::Plus | event::KeyCode::NumpadAdd => {
+                self.change_volume(VOLUME_STEP)
+            }
+            event::KeyCode::Minus | event::KeyCode::NumpadSubtract => {
+                self.change_volume(-VOLUME_STEP)
+            }
+            event::KeyCode::V if keymods.contains(event::KeyMods::CTRL) => {
+                self.import_from_clipboard()
+            }
+            event::KeyCode::F2 => {
+                self.preferences.high_contrast = !self.preferences.high_contrast;
+                self.show_toast(format!(
+                    "High-contrast highlights {}",
+                    if self.preferences.high_contrast {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ));
+                self.preferences.save();
+            }
+            event::KeyCode::F4 => {
+                self.preferences.teaching_mode = !self.preferences.teaching_mode;
+                self.preferences.save();
+            }
+            event::KeyCode::F5 => {
+                self.preferences.last_move_arrow = !self.preferences.last_move_arrow;
+                self.preferences.save();
+            }
+            event::KeyCode::F6 => {
+                self.preferences.last_move_arrow_numbers =
+                    !self.preferences.last_move_arrow_numbers;
+                self.preferences.save();
+            }
+            event::KeyCode::F7 => {
+                self.preferences.only_move_arrow = !self.preferences.only_move_arrow;
+                self.preferences.save();
+            }
+            event::KeyCode::F8 => {
+                self.preferences.promotion_markers = !self.preferences.promotion_markers;
+                self.preferences.save();
+            }
+            event::KeyCode::F9 => {
+                self.preferences.confirm_moves = !self.preferences.confirm_moves;
+                self.pending_move = None;
+                self.preferences.save();
+            }
+            event::KeyCode::F10 => {
+                self.preferences.show_game_phase = !self.preferences.show_game_phase;
+                self.preferences.save();
+            }
+            event::KeyCode::F11 => self.toggle_fullscreen(ctx),
+            event::KeyCode::F12 => {
+                self.preferences.auto_queen = !self.preferences.auto_queen;
+                self.preferences.save();
+            }
+            // developer toggle, on F3 or Ctrl+Shift+D, only available in debug builds
+            event::KeyCode::F3 if cfg!(debug_assertions) => self.debug_grid = !self.debug_grid,
+            event::KeyCode::D
+                if cfg!(debug_assertions)
+                    && keymods.contains(event::KeyMods::CTRL | event::KeyMods::SHIFT) =>
+            {
+                self.debug_grid = !self.debug_grid;
+            }
+            _ => (),
+        }
+    }
+
+    /// Typing ':' opens the command line, and further characters are typed into it
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        match &mut self.command_buffer {
+            Some(command) if !character.is_control() => {
+                command.push(character);
+                self.command_error = None;
+            }
+            Some(_) => (),
+            None if character == ':'
+                && self.pending_resign.is_none()
+                && self.pending_promotion.is_none() =>
+            {
+                self.command_buffer = Some(String::new());
+            }
+            None => return,
+        }
+        self.dirty = true;
+    }
+
+    /// Keep the board centered and undistorted whatever the window's shape
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.dirty = true;
+        let screen = screen_size(self.board_size, self.cell_size);
+        let scale = (width / screen.0).min(height / screen.1);
+        let (view_width, view_height) = (width / scale, height / scale);
+        let view = graphics::Rect::new(
+            -(view_width - screen.0) / 2.0,
+            -(view_height - screen.1) / 2.0,
+            view_width,
+            view_height,
+        );
+        if let Err(err) = graphics::set_screen_coordinates(ctx, view) {
+            eprintln!("Could not update screen coordinates: {}", err);
+        }
+    }
+
+    /// Clean up when the window is closed; returning false lets it close
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.shut_down();
+        false
     }
 }
 
 pub fn main() -> GameResult {
+    let crash_reporter = CrashReporter::install();
+    let options = LaunchOptions::from_args();
+    let screen = screen_size(options.board_size, options.cell_size);
     let resource_dir = path::PathBuf::from("./resources");
 
     let context_builder = ContextBuilder::new("schack", "viola")
@@ -378,11 +3917,192 @@ pub fn main() -> GameResult {
         )
         .window_mode(
             conf::WindowMode::default()
-                .dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1) // Set window dimensions
-                .resizable(false), // Fixate window size
+                .dimensions(screen.0, screen.1) // Set window dimensions
+                .resizable(true), // The board scales with the window
         );
     let (mut contex, event_loop) = context_builder.build().expect("Failed to build context.");
 
-    let state = AppState::new(&mut contex).expect("Failed to create state.");
+    let state =
+        AppState::new(&mut contex, &options, crash_reporter).expect("Failed to create state.");
     event::run(contex, event_loop, state) // Run window event loop
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `moves` from the initial position and reads back the engine's board.
+    fn board_after(moves: &[(&str, &str)]) -> Board {
+        let mut game = Game::new();
+        for (from, to) in moves {
+            Move::new(from.to_string(), to.to_string()).play(&mut game);
+        }
+        read_board(&game)
+    }
+
+    /// (role, is_white) of the piece on a square such as "G1".
+    fn piece_on(board: &Board, square: &str) -> Option<(u8, bool)> {
+        let (row, col) = parse_square(square).unwrap();
+        board[row][col].map(|piece| (piece.role, piece.is_white))
+    }
+
+    #[test]
+    fn undo_restores_the_clocks_of_the_move_taken_back() {
+        // clocks saved when 1. e4, 1... e5 and 2. Nf3 were played
+        let at_e4 = (Duration::from_secs(300), Duration::from_secs(300));
+        let at_e5 = (Duration::from_secs(290), Duration::from_secs(300));
+        let at_nf3 = (Duration::from_secs(290), Duration::from_secs(285));
+        let mut clock_history = vec![at_e4, at_e5, at_nf3];
+
+        // taking back to one move restores the clocks from when 1... e5 was played
+        assert_eq!(rewind_clocks(&mut clock_history, 1), Some(at_e5));
+        assert_eq!(clock_history, vec![at_e4]);
+
+        // playing the moves again saves them again, and undoing round-trips them
+        clock_history.push(at_e5);
+        clock_history.push(at_nf3);
+        assert_eq!(rewind_clocks(&mut clock_history, 2), Some(at_nf3));
+        assert_eq!(rewind_clocks(&mut clock_history, 0), Some(at_e4));
+        assert!(clock_history.is_empty());
+    }
+
+    #[test]
+    fn rewinding_past_the_history_keeps_the_clocks() {
+        let mut clock_history = vec![(Duration::from_secs(60), Duration::from_secs(60))];
+        assert_eq!(rewind_clocks(&mut clock_history, 1), None);
+        assert_eq!(clock_history.len(), 1);
+    }
+
+    #[test]
+    fn castling_kingside_moves_both_rooks() {
+        let board = board_after(&[
+            ("E2", "E4"),
+            ("E7", "E5"),
+            ("G1", "F3"),
+            ("G8", "F6"),
+            ("F1", "C4"),
+            ("F8", "C5"),
+            ("E1", "G1"),
+            ("E8", "G8"),
+        ]);
+        assert_eq!(piece_on(&board, "G1"), Some((KING, true)));
+        assert_eq!(piece_on(&board, "F1"), Some((ROOK, true)));
+        assert_eq!(piece_on(&board, "G8"), Some((KING, false)));
+        assert_eq!(piece_on(&board, "F8"), Some((ROOK, false)));
+        for square in ["E1", "H1", "E8", "H8"] {
+            assert_eq!(piece_on(&board, square), None, "{}", square);
+        }
+    }
+
+    #[test]
+    fn castling_queenside_moves_both_rooks() {
+        let board = board_after(&[
+            ("D2", "D4"),
+            ("D7", "D5"),
+            ("B1", "C3"),
+            ("B8", "C6"),
+            ("C1", "F4"),
+            ("C8", "F5"),
+            ("D1", "D2"),
+            ("D8", "D7"),
+            ("E1", "C1"),
+            ("E8", "C8"),
+        ]);
+        assert_eq!(piece_on(&board, "C1"), Some((KING, true)));
+        assert_eq!(piece_on(&board, "D1"), Some((ROOK, true)));
+        assert_eq!(piece_on(&board, "C8"), Some((KING, false)));
+        assert_eq!(piece_on(&board, "D8"), Some((ROOK, false)));
+        for square in ["E1", "A1", "E8", "A8"] {
+            assert_eq!(piece_on(&board, square), None, "{}", square);
+        }
+    }
+
+    #[test]
+    fn underpromotion_to_a_knight_reaches_the_board() {
+        let mut game = Game::new();
+        let line = [
+            ("E2", "E4"),
+            ("D7", "D5"),
+            ("E4", "D5"),
+            ("C7", "C6"),
+            ("D5", "C6"),
+            ("G8", "F6"),
+            ("C6", "B7"),
+            ("E7", "E6"),
+        ];
+        for (from, to) in line {
+            Move::new(from.to_string(), to.to_string()).play(&mut game);
+        }
+        Move {
+            from: "B7".to_string(),
+            to: "A8".to_string(),
+            promotion: Some(KNIGHT),
+        }
+        .play(&mut game);
+
+        let board = read_board(&game);
+        assert_eq!(piece_on(&board, "A8"), Some((KNIGHT, true)));
+        assert_eq!(piece_on(&board, "B7"), None);
+    }
+
+    #[test]
+    fn parses_the_starting_board() {
+        let board = parse_board(
+            "rnbqkbnr\npppppppp\n********\n********\n********\n********\nPPPPPPPP\nRNBQKBNR\n",
+        );
+        assert_eq!(piece_on(&board, "E1"), Some((KING, true)));
+        assert_eq!(piece_on(&board, "D8"), Some((QUEEN, false)));
+        assert_eq!(piece_on(&board, "G1"), Some((KNIGHT, true)));
+        assert_eq!(piece_on(&board, "C8"), Some((BISHOP, false)));
+        assert_eq!(piece_on(&board, "A2"), Some((PAWN, true)));
+        assert_eq!(piece_on(&board, "E4"), None);
+        assert_eq!(pieces(&board).count(), 32);
+    }
+
+    #[test]
+    fn parsing_ignores_surrounding_whitespace_and_blank_lines() {
+        let board = parse_board(
+            "\n  rnbqkbnr  \n\tpppppppp\n\n********\r\n********\n********\n********\nPPPPPPPP\nRNBQKBNR   \n\n",
+        );
+        assert_eq!(piece_on(&board, "A8"), Some((ROOK, false)));
+        assert_eq!(piece_on(&board, "H1"), Some((ROOK, true)));
+        assert_eq!(pieces(&board).count(), 32);
+    }
+
+    #[test]
+    fn parsing_uneven_lines_fills_what_is_there() {
+        // short ranks leave the rest empty, and anything past eight squares is dropped
+        let board = parse_board("rnbqk\npppppppppp\n\nK");
+        assert_eq!(piece_on(&board, "E8"), Some((KING, false)));
+        assert_eq!(piece_on(&board, "F8"), None);
+        assert_eq!(piece_on(&board, "H7"), Some((PAWN, false)));
+        assert_eq!(piece_on(&board, "A6"), Some((KING, true)));
+        assert_eq!(pieces(&board).count(), 14);
+    }
+
+    #[test]
+    fn adding_colours_clamps_each_channel_and_is_opaque() {
+        let light = Color::new(0.875, 0.9375, 0.75, 1.0);
+        let tint = Color::new(0.25, 0.125, 0.125, 0.5);
+        let sum = AppState::add_color(light, tint);
+        assert_eq!((sum.r, sum.g, sum.b, sum.a), (1.0, 1.0, 0.875, 1.0));
+    }
+
+    #[test]
+    fn promoted_pieces_are_followed_until_captured() {
+        let mut board = empty_board(GRID_SIZE as usize);
+        board[1][0] = Some(Piece::new(PAWN, (1, 0), true));
+        let mut promoted = HashSet::new();
+
+        track_promoted(&mut promoted, &board, (1, 0), (0, 0));
+        assert_eq!(promoted, HashSet::from([(0, 0)]));
+
+        board[0][0] = Some(Piece::new(QUEEN, (0, 0), true));
+        track_promoted(&mut promoted, &board, (0, 0), (4, 4));
+        assert_eq!(promoted, HashSet::from([(4, 4)]));
+
+        board[3][3] = Some(Piece::new(PAWN, (3, 3), false));
+        track_promoted(&mut promoted, &board, (3, 3), (4, 4));
+        assert!(promoted.is_empty());
+    }
+}
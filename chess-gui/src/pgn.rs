@@ -0,0 +1,488 @@
+//! Portable Game Notation: archiving finished games and reading games back in.
+
+use crate::{
+    attacks,
+    coords::{self, parse_square, square_name},
+    pieces, read_board, role_letter, Board, Move, BISHOP, GRID_SIZE, KING, KNIGHT, PAWN, QUEEN,
+    ROOK,
+};
+use jblomlof_chess::{Game, GameState};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Replays `moves` from the starting position and returns each one in
+/// standard algebraic notation.
+pub fn to_san(moves: &[Move]) -> Vec<String> {
+    let mut game = Game::new();
+    let mut san_moves = Vec::new();
+
+    for mv in moves {
+        let before = read_board(&game);
+        let mut san = describe_move(&game, &before, &mv.from, &mv.to);
+
+        mv.play(&mut game);
+        let after = read_board(&game);
+
+        // pawns reaching the last rank show what they became
+        if let (Some(from_sq), Some(to_sq)) = (parse_square(&mv.from), parse_square(&mv.to)) {
+            let was_pawn = before[from_sq.0][from_sq.1].is_some_and(|p| p.role == PAWN);
+            if was_pawn && (to_sq.0 == 0 || to_sq.0 == GRID_SIZE as usize - 1) {
+                if let Some(promoted) = after[to_sq.0][to_sq.1] {
+                    san.push('=');
+                    san.push(role_letter(promoted.role));
+                }
+            }
+        }
+
+        if attacks::in_check(&after, game.is_white_turn()) {
+            if game.get_game_state() == GameState::GameOver {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san_moves.push(san);
+    }
+
+    san_moves
+}
+
+/// Appends a complete PGN record of the game to the file at `path`.
+pub fn append_game(path: &str, moves: &[Move], result: &str) -> io::Result<()> {
+    let mut record = String::new();
+    record.push_str("[Event \"Casual game\"]\n");
+    record.push_str("[Site \"Schack\"]\n");
+    record.push_str(&format!("[Date \"{}\"]\n", today()));
+    record.push_str("[Round \"-\"]\n");
+    record.push_str("[White \"White\"]\n");
+    record.push_str("[Black \"Black\"]\n");
+    record.push_str(&format!("[Result \"{}\"]\n\n", result));
+    record.push_str(&movetext(&to_san(moves), result));
+    record.push_str("\n\n");
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(record.as_bytes())
+}
+
+/// Reads the moves of a PGN game and resolves each against the engine.
+/// Tag pairs, move numbers, comments, variations, annotations and the result
+/// marker are skipped.
+pub fn parse(text: &str) -> Result<Vec<Move>, String> {
+    let mut game = Game::new();
+    let mut moves = Vec::new();
+
+    for token in movetext_tokens(text) {
+        // name the move number so a broken move can be found in the file
+        let move_number = moves.len() / 2 + 1;
+        let mv =
+            resolve_san(&game, &token).map_err(|err| format!("Move {}: {}", move_number, err))?;
+        let was_white_turn = game.is_white_turn();
+        mv.play(&mut game);
+        if game.is_white_turn() == was_white_turn {
+            return Err(format!(
+                "Move {}: {} is not a legal move here",
+                move_number, token
+            ));
+        }
+        moves.push(mv);
+    }
+
+    Ok(moves)
+}
+
+/// Splits PGN text into its SAN move tokens.
+fn movetext_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_tag = false;
+    let mut in_comment = false;
+    let mut in_line_comment = false;
+    let mut variation_depth = 0;
+
+    for c in text.chars() {
+        if in_line_comment {
+            in_line_comment = c != '\n';
+            continue;
+        }
+        if in_comment {
+            in_comment = c != '}';
+            continue;
+        }
+        if in_tag {
+            in_tag = c != ']';
+            continue;
+        }
+
+        match c {
+            '[' | '{' | ';' | '(' | ')' => {
+                flush_token(&mut current, &mut tokens);
+                match c {
+                    '[' => in_tag = true,
+                    '{' => in_comment = true,
+                    ';' => in_line_comment = true,
+                    '(' => variation_depth += 1,
+                    _ => variation_depth = (variation_depth - 1).max(0),
+                }
+            }
+            _ if variation_depth > 0 => (),
+            c if c.is_whitespace() => flush_token(&mut current, &mut tokens),
+            c => current.push(c),
+        }
+    }
+    flush_token(&mut current, &mut tokens);
+
+    tokens
+}
+
+/// Moves `current` into `tokens` unless it is empty, a result marker or an
+/// annotation, dropping any move number glued to its front ("12.e4").
+fn flush_token(current: &mut String, tokens: &mut Vec<String>) {
+    let token = std::mem::take(current);
+    if matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") || token.starts_with('$') {
+        return;
+    }
+    // castling written with zeros starts with a digit too
+    let token = if token.starts_with("0-0") {
+        token.as_str()
+    } else {
+        token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+    };
+    if !token.is_empty() {
+        tokens.push(token.to_string());
+    }
+}
+
+/// Finds the engine move for a SAN move in the current position.
+fn resolve_san(game: &Game, san: &str) -> Result<Move, String> {
+    let white = game.is_white_turn();
+    let clean = san.trim_end_matches(['+', '#', '!', '?']);
+    let home_rank = if white { 1 } else { 8 };
+    let board = read_board(game);
+
+    // long algebraic moves ("e2e4", "e7e8n") name both squares outright
+    if let Some((from, to)) = coords::parse_uci_move(clean) {
+        return Ok(with_promotion(&board, from, to, clean.chars().nth(4)));
+    }
+
+    match clean {
+        "O-O" | "0-0" => {
+            return Ok(Move::new(
+                format!("E{}", home_rank),
+                format!("G{}", home_rank),
+            ))
+        }
+        "O-O-O" | "0-0-0" => {
+            return Ok(Move::new(
+                format!("E{}", home_rank),
+                format!("C{}", home_rank),
+            ))
+        }
+        _ => (),
+    }
+
+    // the promotion piece follows an '=', or comes straight after the square
+    let (body, promotion) = match clean.find('=') {
+        Some(index) => (&clean[..index], clean[index + 1..].chars().next()),
+        None => {
+            let body = clean.trim_end_matches(['Q', 'R', 'B', 'N']);
+            (body, clean[body.len()..].chars().next())
+        }
+    };
+    if body.len() < 2 || !body.is_ascii() {
+        return Err(format!("Can't read move {}", san));
+    }
+
+    let (prefix, destination) = body.split_at(body.len() - 2);
+    let to_sq = parse_square(destination).ok_or_else(|| format!("Can't read move {}", san))?;
+    let (role, hints) = match prefix.chars().next() {
+        Some('K') => (KING, &prefix[1..]),
+        Some('Q') => (QUEEN, &prefix[1..]),
+        Some('R') => (ROOK, &prefix[1..]),
+        Some('B') => (BISHOP, &prefix[1..]),
+        Some('N') => (KNIGHT, &prefix[1..]),
+        _ => (PAWN, prefix),
+    };
+    // disambiguation by starting file and/or rank, e.g. "Nbd7", "R1e2", "exd5"
+    let file_hint = hints
+        .chars()
+        .find(|c| ('a'..='h').contains(c))
+        .map(|c| c as usize - 'a' as usize);
+    let row_hint = hints
+        .chars()
+        .find_map(|c| c.to_digit(10))
+        .map(|rank| GRID_SIZE as usize - rank as usize);
+
+    let to = square_name(to_sq);
+    let candidates: Vec<(usize, usize)> = pieces(&board)
+        .filter(|(square, piece)| {
+            piece.role == role
+                && piece.is_white == white
+                && file_hint.map_or(true, |col| square.1 == col)
+                && row_hint.map_or(true, |row| square.0 == row)
+        })
+        .map(|(square, _)| square)
+        .filter(|square| {
+            game.get_possible_moves(&square_name(*square))
+                .is_some_and(|moves| moves.iter().any(|m| m.eq_ignore_ascii_case(&to)))
+        })
+        .collect();
+
+    match candidates.as_slice() {
+        [from] => Ok(with_promotion(&board, *from, to_sq, promotion)),
+        [] => Err(format!("{} is not a legal move here", san)),
+        _ => Err(format!("{} is ambiguous", san)),
+    }
+}
+
+/// The move between two (row, column) squares. A pawn reaching the last rank
+/// promotes to the piece named by `letter`, or to a queen if none is named.
+pub fn with_promotion(
+    board: &Board,
+    from: (usize, usize),
+    to: (usize, usize),
+    letter: Option<char>,
+) -> Move {
+    let promotes = board[from.0][from.1].is_some_and(|piece| piece.role == PAWN)
+        && (to.0 == 0 || to.0 == GRID_SIZE as usize - 1);
+    let promotion = promotes.then(|| match letter.map(|c| c.to_ascii_uppercase()) {
+        Some('R') => ROOK,
+        Some('B') => BISHOP,
+        Some('N') => KNIGHT,
+        _ => QUEEN,
+    });
+
+    Move {
+        from: square_name(from),
+        to: square_name(to),
+        promotion,
+    }
+}
+
+/// Numbered movetext ("1. e4 e5 2. Nf3 ...") ending with the result, wrapped at 80 columns.
+fn movetext(san_moves: &[String], result: &str) -> String {
+    let mut tokens = Vec::new();
+    for (ply, san) in san_moves.iter().enumerate() {
+        if ply % 2 == 0 {
+            tokens.push(format!("{}.", ply / 2 + 1));
+        }
+        tokens.push(san.clone());
+    }
+    tokens.push(result.to_string());
+
+    let mut text = String::new();
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > 80 {
+            text.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+        line_length += token.len();
+        text.push_str(&token);
+    }
+    text
+}
+
+/// Standard algebraic notation for a move, without check or promotion suffixes.
+fn describe_move(game: &Game, board: &Board, from: &str, to: &str) -> String {
+    let (from_sq, to_sq) = match (parse_square(from), parse_square(to)) {
+        (Some(from_sq), Some(to_sq)) => (from_sq, to_sq),
+        _ => return format!("{}{}", from, to).to_ascii_lowercase(),
+    };
+    let piece = match board[from_sq.0][from_sq.1] {
+        Some(piece) => piece,
+        None => return format!("{}{}", from, to).to_ascii_lowercase(),
+    };
+
+    if piece.role == KING && (from_sq.1 as i16 - to_sq.1 as i16).abs() == 2 {
+        return if to_sq.1 > from_sq.1 { "O-O" } else { "O-O-O" }.to_string();
+    }
+
+    // a pawn changing file always captures, even onto an empty en passant square
+    let capture = board[to_sq.0][to_sq.1].is_some() || (piece.role == PAWN && from_sq.1 != to_sq.1);
+    let destination = to.to_ascii_lowercase();
+
+    if piece.role == PAWN {
+        return if capture {
+            format!("{}x{}", file_letter(from_sq.1), destination)
+        } else {
+            destination
+        };
+    }
+
+    // other pieces of the same kind that could also reach the destination
+    let rivals: Vec<(usize, usize)> = pieces(board)
+        .filter(|(square, other)| {
+            *square != from_sq && other.role == piece.role && other.is_white == piece.is_white
+        })
+        .map(|(square, _)| square)
+        .filter(|square| {
+            game.get_possible_moves(&square_name(*square))
+                .is_some_and(|moves| moves.iter().any(|m| m.eq_ignore_ascii_case(to)))
+        })
+        .collect();
+
+    let mut san = role_letter(piece.role).to_string();
+    if !rivals.is_empty() {
+        if rivals.iter().all(|rival| rival.1 != from_sq.1) {
+            san.push(file_letter(from_sq.1));
+        } else if rivals.iter().all(|rival| rival.0 != from_sq.0) {
+            san.push_str(&(GRID_SIZE as usize - from_sq.0).to_string());
+        } else {
+            san.push_str(&from.to_ascii_lowercase());
+        }
+    }
+    if capture {
+        san.push('x');
+    }
+    san.push_str(&destination);
+    san
+}
+
+fn file_letter(col: usize) -> char {
+    (b'a' + col as u8) as char
+}
+
+/// Today's date in PGN's "YYYY.MM.DD" form (UTC).
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400) as i64;
+    date_after_epoch(days)
+}
+
+/// The date `days` days after 1970-01-01 in PGN's "YYYY.MM.DD" form, after
+/// Howard Hinnant's civil date algorithm.
+fn date_after_epoch(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (from, to) of each move, in the engine's square names.
+    fn squares(moves: &[Move]) -> Vec<(&str, &str)> {
+        moves
+            .iter()
+            .map(|mv| (mv.from.as_str(), mv.to.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn movetext_numbers_each_white_move_and_ends_with_the_result() {
+        let san: Vec<String> = ["e4", "e5", "Nf3"].iter().map(|m| m.to_string()).collect();
+        assert_eq!(movetext(&san, "*"), "1. e4 e5 2. Nf3 *");
+        assert_eq!(movetext(&[], "1/2-1/2"), "1/2-1/2");
+    }
+
+    #[test]
+    fn movetext_wraps_at_80_columns() {
+        let san: Vec<String> = (0..60).map(|_| "Nf3".to_string()).collect();
+        let text = movetext(&san, "1-0");
+        assert!(text.lines().count() > 1);
+        assert!(text.lines().all(|line| line.len() <= 80), "{}", text);
+
+        // wrapping only turns spaces into line breaks
+        let unwrapped: Vec<String> = (1..=30)
+            .map(|number| format!("{}. Nf3 Nf3", number))
+            .collect();
+        assert_eq!(text.replace('\n', " "), unwrapped.join(" ") + " 1-0");
+    }
+
+    #[test]
+    fn dates_count_from_the_epoch() {
+        assert_eq!(date_after_epoch(0), "1970.01.01");
+        assert_eq!(date_after_epoch(-1), "1969.12.31");
+        assert_eq!(date_after_epoch(11_016), "2000.02.29");
+        assert_eq!(date_after_epoch(11_017), "2000.03.01");
+        assert_eq!(date_after_epoch(20_088), "2024.12.31");
+        assert_eq!(date_after_epoch(47_541), "2100.03.01");
+    }
+
+    #[test]
+    fn today_is_a_pgn_date() {
+        let date = today();
+        assert_eq!(date.len(), 10);
+        assert_eq!(date.matches('.').count(), 2);
+    }
+
+    #[test]
+    fn tokens_skip_move_numbers() {
+        assert_eq!(
+            movetext_tokens("1. e4 e5 2.Nf3 2... Nc6 3 Bb5"),
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5"]
+        );
+    }
+
+    #[test]
+    fn tokens_skip_tags_comments_variations_and_annotations() {
+        let text = "[Event \"Casual game\"]\n[Site \"Schack\"]\n\n\
+                    1. e4 {the best move} e5 ; a comment to the end of the line\n\
+                    2. Nf3 (2. f4 exf4 (2... d5)) $1 Nc6";
+        assert_eq!(movetext_tokens(text), vec!["e4", "e5", "Nf3", "Nc6"]);
+    }
+
+    #[test]
+    fn tokens_skip_result_markers() {
+        for result in ["1-0", "0-1", "1/2-1/2", "*"] {
+            let text = format!("1. e4 e5 {}", result);
+            assert_eq!(movetext_tokens(&text), vec!["e4", "e5"], "{}", result);
+        }
+    }
+
+    #[test]
+    fn castling_with_zeros_is_not_a_move_number() {
+        assert_eq!(movetext_tokens("5. 0-0 0-0-0"), vec!["0-0", "0-0-0"]);
+    }
+
+    #[test]
+    fn parses_a_game_with_comments_and_a_result() {
+        let moves = parse("1. e4 {open} e5 2. Nf3 ; develop\nNc6 1/2-1/2").unwrap();
+        assert_eq!(
+            squares(&moves),
+            vec![("E2", "E4"), ("E7", "E5"), ("G1", "F3"), ("B8", "C6")]
+        );
+    }
+
+    #[test]
+    fn disambiguation_by_file_picks_the_named_knight() {
+        let moves = parse("1. d4 d5 2. Nf3 Nf6 3. Nbd2").unwrap();
+        assert_eq!(squares(&moves).last().copied(), Some(("B1", "D2")));
+    }
+
+    #[test]
+    fn disambiguation_by_rank_picks_the_named_knight() {
+        // knights on b1 and b3 can both reach d2
+        let text = "1. d4 Nf6 2. Nd2 Ng8 3. Nb3 Nf6 4. e3 Ng8 5. Ne2 Nf6 \
+                    6. Nc3 Ng8 7. Nb1 Nf6 8. N1d2";
+        let moves = parse(text).unwrap();
+        assert_eq!(squares(&moves).last().copied(), Some(("B1", "D2")));
+    }
+
+    #[test]
+    fn an_illegal_move_names_its_move_number() {
+        let err = parse("1. e4 e5 2. Ke3").unwrap_err();
+        assert!(err.starts_with("Move 2:"), "{}", err);
+    }
+}
@@ -0,0 +1,321 @@
+//! User preferences, persisted as TOML next to the executable's working directory.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, time::Duration};
+
+const PREFERENCES_PATH: &str = "preferences.toml";
+/// Longest clock a game can be given, a day, well short of overflowing the
+/// clock's seconds.
+const MAX_CLOCK_MINUTES: u64 = 24 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// PGN file that finished games are appended to.
+    pub archive_path: String,
+    /// Use the right mouse button for moving pieces, for left-handed layouts.
+    pub swap_buttons: bool,
+    /// Draw an arrow along the last move played.
+    pub last_move_arrow: bool,
+    /// Label the last-move arrow with its move number.
+    pub last_move_arrow_numbers: bool,
+    /// Point an arrow at the destination of a selected piece with a single legal move.
+    pub only_move_arrow: bool,
+    /// Mark pieces that came from a pawn promotion.
+    pub promotion_markers: bool,
+    /// Promote pawns to a queen straight away instead of asking which piece.
+    pub auto_queen: bool,
+    /// Ask for a second click on the destination before playing a move.
+    pub confirm_moves: bool,
+    /// Turn the board after every move so the side to move is at the bottom.
+    pub auto_flip: bool,
+    /// Silence every sound effect.
+    pub muted: bool,
+    /// Loudness of the sound effects, from 0 to 1.
+    pub volume: f32,
+    /// Show whether the game is in the opening, middlegame or endgame.
+    pub show_game_phase: bool,
+    /// Make the running clock larger and brighter than the other one, rather
+    /// than showing both alike.
+    pub emphasize_active_clock: bool,
+    /// Minutes on each side's clock at the start of a game.
+    pub clock_minutes: u64,
+    /// Teaching aids, such as showing whether a hovered piece is defended or attacked.
+    pub teaching_mode: bool,
+    /// Speed multiplier for board effects; 0 turns them off.
+    pub animation_speed: f32,
+    /// Where square coordinates are written on the board.
+    pub coordinates: CoordinateMode,
+    /// Name of the board theme: brown, green or blue.
+    pub board_theme: String,
+    /// Colour of the light tiles in place of the board theme's.
+    pub light_tiles: Option<[f32; 4]>,
+    /// Colour of the dark tiles in place of the board theme's.
+    pub dark_tiles: Option<[f32; 4]>,
+    /// Name of the piece sprite theme: "default" or a directory under resources.
+    pub sprite_theme: String,
+    /// Colours of the board highlights.
+    pub highlight_colors: HighlightColors,
+    /// Use the high-contrast palette in place of `highlight_colors`, for
+    /// players who find the usual tints hard to tell apart.
+    pub high_contrast: bool,
+}
+
+/// Ways of labelling the board with square coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoordinateMode {
+    /// No labels.
+    Off,
+    /// Files along the bottom row and ranks along the left column, inside the tile corners.
+    EdgeTiles,
+    /// The full square name in the corner of every tile.
+    EveryTile,
+}
+
+impl CoordinateMode {
+    /// The mode after this one, wrapping around.
+    pub fn next(self) -> CoordinateMode {
+        match self {
+            CoordinateMode::Off => CoordinateMode::EdgeTiles,
+            CoordinateMode::EdgeTiles => CoordinateMode::EveryTile,
+            CoordinateMode::EveryTile => CoordinateMode::Off,
+        }
+    }
+}
+
+/// RGBA colours, each component from 0 to 1, for the kinds of board highlight.
+/// Tile highlights are added onto the tile's own colour, while move markers
+/// are drawn over it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightColors {
+    /// Tile of the selected piece.
+    pub selection: [f32; 4],
+    /// Dots on empty tiles the selected piece can move to.
+    pub legal_move: [f32; 4],
+    /// Rings around pieces the selected piece can capture.
+    pub capture: [f32; 4],
+    /// Tiles the selected piece attacks, whether or not it can move there.
+    pub attack: [f32; 4],
+    /// Tile of the rook that would castle with the selected king.
+    pub castling_rook: [f32; 4],
+    /// Arrow along the last move.
+    pub last_move: [f32; 4],
+    /// Tiles the last move went from and to.
+    pub last_move_tiles: [f32; 4],
+    /// Tile of a king in check.
+    pub check: [f32; 4],
+    /// Tile under the mouse.
+    pub hover: [f32; 4],
+}
+
+impl Default for HighlightColors {
+    fn default() -> Self {
+        HighlightColors {
+            selection: [40.0 / 255.0, 90.0 / 255.0, 80.0 / 255.0, 0.3],
+            legal_move: [40.0 / 255.0, 90.0 / 255.0, 80.0 / 255.0, 0.6],
+            capture: [110.0 / 255.0, 30.0 / 255.0, 20.0 / 255.0, 0.6],
+            attack: [40.0 / 255.0, 60.0 / 255.0, 130.0 / 255.0, 0.15],
+            castling_rook: [150.0 / 255.0, 110.0 / 255.0, 0.0, 0.35],
+            last_move: [230.0 / 255.0, 150.0 / 255.0, 30.0 / 255.0, 0.7],
+            last_move_tiles: [50.0 / 255.0, 50.0 / 255.0, 0.0, 0.3],
+            check: [150.0 / 255.0, 0.0, 0.0, 0.5],
+            hover: [20.0 / 255.0, 20.0 / 255.0, 20.0 / 255.0, 0.2],
+        }
+    }
+}
+
+impl HighlightColors {
+    /// Opaque colours from the Okabe–Ito palette, which stay distinct under
+    /// the common kinds of colour blindness. Tiles take these colours outright
+    /// rather than having them added on.
+    pub fn high_contrast() -> Self {
+        HighlightColors {
+            selection: [0.0, 114.0 / 255.0, 178.0 / 255.0, 1.0],
+            legal_move: [0.0, 114.0 / 255.0, 178.0 / 255.0, 0.9],
+            capture: [204.0 / 255.0, 121.0 / 255.0, 167.0 / 255.0, 0.9],
+            attack: [86.0 / 255.0, 180.0 / 255.0, 233.0 / 255.0, 1.0],
+            castling_rook: [0.0, 158.0 / 255.0, 115.0 / 255.0, 1.0],
+            last_move: [230.0 / 255.0, 159.0 / 255.0, 0.0, 0.9],
+            last_move_tiles: [240.0 / 255.0, 228.0 / 255.0, 66.0 / 255.0, 1.0],
+            check: [213.0 / 255.0, 94.0 / 255.0, 0.0, 1.0],
+            hover: [0.6, 0.6, 0.6, 1.0],
+        }
+    }
+
+    /// Clamps every component into 0 to 1, reporting any that were out of range.
+    fn validate(&mut self) {
+        let colors = [
+            ("selection", &mut self.selection),
+            ("legal_move", &mut self.legal_move),
+            ("capture", &mut self.capture),
+            ("attack", &mut self.attack),
+            ("castling_rook", &mut self.castling_rook),
+            ("last_move", &mut self.last_move),
+            ("last_move_tiles", &mut self.last_move_tiles),
+            ("check", &mut self.check),
+            ("hover", &mut self.hover),
+        ];
+        for (name, color) in colors {
+            clamp_color(&format!("Highlight colour {}", name), color);
+        }
+    }
+}
+
+/// Clamps every component of a colour into 0 to 1, reporting it if any were out of range.
+fn clamp_color(name: &str, color: &mut [f32; 4]) {
+    if color
+        .iter()
+        .any(|component| !(0.0..=1.0).contains(component))
+    {
+        eprintln!("{} is out of range, clamping it", name);
+        for component in color.iter_mut() {
+            // NaN fails every comparison, so treat it as 0
+            *component = if component.is_nan() {
+                0.0
+            } else {
+                component.clamp(0.0, 1.0)
+            };
+        }
+    }
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            archive_path: "games.pgn".to_string(),
+            swap_buttons: false,
+            last_move_arrow: true,
+            last_move_arrow_numbers: false,
+            only_move_arrow: true,
+            promotion_markers: false,
+            auto_queen: false,
+            confirm_moves: false,
+            auto_flip: false,
+            show_game_phase: true,
+            muted: false,
+            volume: 1.0,
+            emphasize_active_clock: false,
+            clock_minutes: 10,
+            teaching_mode: false,
+            animation_speed: 1.0,
+            coordinates: CoordinateMode::Off,
+            board_theme: "brown".to_string(),
+            light_tiles: None,
+            dark_tiles: None,
+            sprite_theme: "default".to_string(),
+            highlight_colors: HighlightColors::default(),
+            high_contrast: false,
+        }
+    }
+}
+
+impl Preferences {
+    /// Reads the preferences file, falling back to defaults if it is missing or malformed.
+    pub fn load() -> Preferences {
+        let mut preferences = match fs::read_to_string(PREFERENCES_PATH) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|err| {
+                eprintln!("Ignoring malformed {}: {}", PREFERENCES_PATH, err);
+                Preferences::default()
+            }),
+            Err(_) => Preferences::default(),
+        };
+        preferences.highlight_colors.validate();
+        if let Some(light) = &mut preferences.light_tiles {
+            clamp_color("Light tile colour", light);
+        }
+        if let Some(dark) = &mut preferences.dark_tiles {
+            clamp_color("Dark tile colour", dark);
+        }
+        if !(0.0..=1.0).contains(&preferences.volume) {
+            eprintln!("volume must be between 0 and 1, clamping it");
+            // NaN fails every comparison, so treat it as full volume
+            preferences.volume = if preferences.volume.is_nan() {
+                1.0
+            } else {
+                preferences.volume.clamp(0.0, 1.0)
+            };
+        }
+        if !(1..=MAX_CLOCK_MINUTES).contains(&preferences.clock_minutes) {
+            eprintln!(
+                "clock_minutes must be from 1 to {}, using the default",
+                MAX_CLOCK_MINUTES
+            );
+            preferences.clock_minutes = Preferences::default().clock_minutes;
+        }
+        preferences
+    }
+
+    /// Colours of the board highlights in use, which are the high-contrast
+    /// palette while it is switched on.
+    pub fn highlight_colors(&self) -> HighlightColors {
+        if self.high_contrast {
+            HighlightColors::high_contrast()
+        } else {
+            self.highlight_colors.clone()
+        }
+    }
+
+    /// Volume to play sound effects at, 0 when they are muted.
+    pub fn sound_volume(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+
+    /// Time each side's clock starts with.
+    pub fn clock_time(&self) -> Duration {
+        Duration::from_secs(self.clock_minutes * 60)
+    }
+
+    /// Writes the preferences file, reporting rather than failing on errors.
+    /// The file is written aside and then renamed into place, so being closed
+    /// mid-write can't leave a truncated file behind.
+    pub fn save(&self) {
+        let staging_path = format!("{}.tmp", PREFERENCES_PATH);
+        let result = toml::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                fs::write(&staging_path, contents)
+                    .and_then(|()| fs::rename(&staging_path, PREFERENCES_PATH))
+                    .map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            eprintln!("Could not save {}: {}", PREFERENCES_PATH, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_components_are_clamped() {
+        let mut color = [1.5, -0.2, 0.5, f32::NAN];
+        clamp_color("test colour", &mut color);
+        assert_eq!(color, [1.0, 0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn colours_in_range_are_left_alone() {
+        let mut color = [0.0, 0.25, 1.0, 0.6];
+        clamp_color("test colour", &mut color);
+        assert_eq!(color, [0.0, 0.25, 1.0, 0.6]);
+    }
+
+    #[test]
+    fn validating_highlights_clamps_only_the_bad_colour() {
+        let mut colors = HighlightColors {
+            capture: [2.0, 0.5, -1.0, 0.6],
+            ..HighlightColors::default()
+        };
+        colors.validate();
+        assert_eq!(colors.capture, [1.0, 0.5, 0.0, 0.6]);
+        assert_eq!(colors.selection, HighlightColors::default().selection);
+        assert_eq!(colors.hover, HighlightColors::default().hover);
+    }
+}
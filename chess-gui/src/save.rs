@@ -0,0 +1,53 @@
+//! Saving a game in progress as JSON and resuming it later.
+
+use crate::Move;
+use jblomlof_chess::Game;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// What is written to the save file.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedGame {
+    /// Moves played from the starting position.
+    moves: Vec<Move>,
+    /// Side to move after the moves, checked when loading.
+    white_to_move: bool,
+}
+
+/// Writes the moves played so far to `path`.
+pub fn save(path: &str, moves: &[Move], white_to_move: bool) -> Result<(), String> {
+    let saved = SavedGame {
+        moves: moves.to_vec(),
+        white_to_move,
+    };
+    let contents = serde_json::to_string_pretty(&saved).map_err(|err| err.to_string())?;
+    fs::write(path, contents).map_err(|err| format!("{}: {}", path, err))
+}
+
+/// Reads the moves saved in `path`, making sure every one of them is legal
+/// when replayed and that they leave the recorded side to move.
+pub fn load(path: &str) -> Result<Vec<Move>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let saved: SavedGame =
+        serde_json::from_str(&contents).map_err(|err| format!("{}: {}", path, err))?;
+
+    let mut game = Game::new();
+    for (ply, mv) in saved.moves.iter().enumerate() {
+        let was_white_turn = game.is_white_turn();
+        mv.play(&mut game);
+        // the engine hands the turn over only when the move was accepted
+        if game.is_white_turn() == was_white_turn {
+            return Err(format!(
+                "Move {} ({}-{}) is not legal",
+                ply / 2 + 1,
+                mv.from,
+                mv.to
+            ));
+        }
+    }
+    if game.is_white_turn() != saved.white_to_move {
+        return Err("The saved side to move doesn't match the moves".to_string());
+    }
+
+    Ok(saved.moves)
+}
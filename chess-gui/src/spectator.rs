@@ -0,0 +1,78 @@
+//! Tiny HTTP endpoint serving the current position, for stream overlays to poll.
+
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+use tiny_http::{Header, Response, Server};
+
+pub struct Spectator {
+    // latest position, shared with the server thread
+    fen: Arc<Mutex<String>>,
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Spectator {
+    /// Starts answering every request on `host`:`port` with the current FEN as
+    /// plain text. Returns `None`, after reporting why, if the address can't be bound.
+    pub fn start(host: &str, port: u16, fen: String) -> Option<Spectator> {
+        let server = match Server::http((host, port)) {
+            Ok(server) => Arc::new(server),
+            Err(err) => {
+                eprintln!("Could not serve the position on {}:{}: {}", host, port, err);
+                return None;
+            }
+        };
+        let fen = Arc::new(Mutex::new(fen));
+
+        let handle = {
+            let server = Arc::clone(&server);
+            let fen = Arc::clone(&fen);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let body = fen.lock().map(|fen| fen.clone()).unwrap_or_default();
+                    let response = Response::from_string(body)
+                        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+                        .with_header(header("Access-Control-Allow-Origin", "*"));
+                    if let Err(err) = request.respond(response) {
+                        eprintln!("Could not answer spectator request: {}", err);
+                    }
+                }
+            })
+        };
+
+        println!(
+            "Serving the current position at http://{}/",
+            server.server_addr()
+        );
+        Some(Spectator {
+            fen,
+            server,
+            handle: Some(handle),
+        })
+    }
+
+    /// Replaces the position handed out to spectators.
+    pub fn publish(&self, fen: String) {
+        if let Ok(mut current) = self.fen.lock() {
+            *current = fen;
+        }
+    }
+}
+
+impl Drop for Spectator {
+    /// Stops the server thread so it doesn't outlive the window.
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                eprintln!("Spectator server thread panicked.");
+            }
+        }
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("Static header is valid.")
+}
@@ -0,0 +1,77 @@
+//! Opening trainer: drills the player on a book line read from a PGN file.
+
+use crate::{coords::parse_square, pgn, role_name, Board, Move};
+use std::fs;
+
+/// A book line and the side the player is practising.
+#[derive(Debug, Clone)]
+pub struct Trainer {
+    line: Vec<Move>,
+    pub player_is_white: bool,
+}
+
+/// What the trainer makes of a move the player tried.
+pub enum Verdict {
+    /// The move follows the book, or the line is already over.
+    Accepted,
+    /// The move leaves the book; holds a hint towards the right one.
+    Rejected(String),
+}
+
+impl Trainer {
+    /// Reads the line from the moves of a PGN file.
+    pub fn load(path: &str, player_is_white: bool) -> Result<Trainer, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let line = pgn::parse(&text)?;
+        if line.is_empty() {
+            return Err(format!("{} holds no moves", path));
+        }
+        Ok(Trainer {
+            line,
+            player_is_white,
+        })
+    }
+
+    /// Number of half-moves in the line.
+    pub fn len(&self) -> usize {
+        self.line.len()
+    }
+
+    /// Book move for the given ply, if the line goes that far.
+    pub fn book_move(&self, ply: usize) -> Option<&Move> {
+        self.line.get(ply)
+    }
+
+    /// Whether the book plays the move at this ply rather than the player.
+    pub fn book_to_move(&self, ply: usize) -> bool {
+        let white_to_move = ply % 2 == 0;
+        ply < self.line.len() && white_to_move != self.player_is_white
+    }
+
+    /// Checks the player's move at `ply` against the book.
+    pub fn check(&self, ply: usize, played: &Move, board: &Board) -> Verdict {
+        let book = match self.book_move(ply) {
+            Some(book) if book != played => book,
+            _ => return Verdict::Accepted,
+        };
+
+        if (&book.from, &book.to) == (&played.from, &played.to) {
+            if let Some(role) = book.promotion {
+                return Verdict::Rejected(format!(
+                    "Not the book move. Hint: promote to a {}",
+                    role_name(role)
+                ));
+            }
+        }
+
+        let piece = parse_square(&book.from).and_then(|(row, col)| board[row][col]);
+        Verdict::Rejected(match piece {
+            Some(piece) => format!(
+                "Not the book move. Hint: move the {} on {}",
+                role_name(piece.role),
+                book.from.to_lowercase()
+            ),
+            None => "Not the book move".to_string(),
+        })
+    }
+}